@@ -0,0 +1,21 @@
+//! Opens arbitrary bytes as an archive and reads all entries
+//! (`cargo fuzz run par_read`, regression inputs are in `corpus/par_read`)
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate peridot_archive as par;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut archive) = par::ArchiveRead::from_bytes(data.to_vec(), false) {
+        archive.set_entry_verification(true);
+        let names: Vec<String> = archive.entry_names().map(ToOwned::to_owned).collect();
+        for n in names {
+            let _ = archive.read_slice(&n);
+            let _ = archive.read_bin(&n);
+        }
+    }
+    if let Ok(mut view) = par::ArchiveView::new(data, false) {
+        view.set_entry_verification(true);
+        for n in view.entry_names() { let _ = view.read_slice(n); }
+    }
+});
@@ -0,0 +1,13 @@
+//! Reads arbitrary bytes as a vertex processing pack(`cargo fuzz run pvp_read`)
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate peridot_vertex_processing_pack as pvp;
+
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(reader) = pvp::PvpContainerReader::new(Cursor::new(data)) {
+        let _ = reader.into_container();
+    }
+});
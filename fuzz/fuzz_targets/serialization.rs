@@ -0,0 +1,31 @@
+//! Reads arbitrary bytes as varints and Pascal strings, checking that decoded values are written back as read
+//! (`cargo fuzz run serialization`)
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate peridot_serialization_utils;
+
+use peridot_serialization_utils::*;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(VariableUInt(v)) = VariableUInt::read(&mut Cursor::new(data)) {
+        let mut bytes = Vec::new(); VariableUInt(v).write(&mut bytes).unwrap();
+        assert_eq!(VariableUInt::read(&mut Cursor::new(bytes)).unwrap().0, v);
+    }
+    if let Ok(VariableUInt64(v)) = VariableUInt64::read(&mut Cursor::new(data)) {
+        let mut bytes = Vec::new(); VariableUInt64(v).write(&mut bytes).unwrap();
+        assert_eq!(VariableUInt64::read(&mut Cursor::new(bytes)).unwrap().0, v);
+    }
+    if let Ok(VariableInt64(v)) = VariableInt64::read(&mut Cursor::new(data)) {
+        let mut bytes = Vec::new(); VariableInt64(v).write(&mut bytes).unwrap();
+        assert_eq!(VariableInt64::read(&mut Cursor::new(bytes)).unwrap().0, v);
+    }
+
+    let mut reader = Cursor::new(data);
+    while let Ok(PascalString(s)) = PascalString::read(&mut reader) {
+        let mut bytes = Vec::new(); PascalStr(&s).write(&mut bytes).unwrap();
+        assert_eq!(PascalString::read(&mut Cursor::new(bytes)).unwrap().0, s);
+    }
+    let _ = Vec::<String>::deserialize(&mut Cursor::new(data));
+});
@@ -0,0 +1,78 @@
+//! Per-entry Checksums(random-access archives only)
+
+use crc::crc32;
+use xxhash_rust::xxh64::Xxh64;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Algorithm of checksums recorded for each entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumMethod { None, CRC32, XXH64 }
+impl ChecksumMethod {
+    pub(crate) fn hasher(&self) -> EntryHasher {
+        match *self {
+            ChecksumMethod::None => EntryHasher::None,
+            ChecksumMethod::CRC32 => EntryHasher::CRC32(0),
+            ChecksumMethod::XXH64 => EntryHasher::XXH64(Xxh64::new(0))
+        }
+    }
+    /// Checksum of the bytes on memory
+    pub fn checksum(&self, bytes: &[u8]) -> EntryChecksum {
+        let mut h = self.hasher(); h.update(bytes); h.finish()
+    }
+}
+
+/// Checksum of the stored(compressed) bytes of an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryChecksum { None, CRC32(u32), XXH64(u64) }
+impl EntryChecksum {
+    pub fn method(&self) -> ChecksumMethod {
+        match *self {
+            EntryChecksum::None => ChecksumMethod::None,
+            EntryChecksum::CRC32(_) => ChecksumMethod::CRC32,
+            EntryChecksum::XXH64(_) => ChecksumMethod::XXH64
+        }
+    }
+    pub(crate) fn tag(&self) -> u8 {
+        match *self { EntryChecksum::None => 0, EntryChecksum::CRC32(_) => 1, EntryChecksum::XXH64(_) => 2 }
+    }
+    /// 値はどの方式でも8バイトで記録する
+    pub(crate) fn value(&self) -> u64 {
+        match *self { EntryChecksum::None => 0, EntryChecksum::CRC32(v) => v as _, EntryChecksum::XXH64(v) => v }
+    }
+    pub(crate) fn from_tag(tag: u8, value: u64) -> Option<Self> {
+        match tag {
+            0 => Some(EntryChecksum::None),
+            1 => Some(EntryChecksum::CRC32(value as _)),
+            2 => Some(EntryChecksum::XXH64(value)),
+            _ => None
+        }
+    }
+}
+impl Display for EntryChecksum {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match *self {
+            EntryChecksum::None => write!(fmt, "none"),
+            EntryChecksum::CRC32(v) => write!(fmt, "CRC-32:0x{:08x}", v),
+            EntryChecksum::XXH64(v) => write!(fmt, "XXH64:0x{:016x}", v)
+        }
+    }
+}
+
+/// Computes a checksum incrementally
+pub(crate) enum EntryHasher { None, CRC32(u32), XXH64(Xxh64) }
+impl EntryHasher {
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        match *self {
+            EntryHasher::None => (),
+            EntryHasher::CRC32(ref mut c) => *c = crc32::update(*c, &crc32::IEEE_TABLE, bytes),
+            EntryHasher::XXH64(ref mut h) => h.update(bytes)
+        }
+    }
+    pub(crate) fn finish(self) -> EntryChecksum {
+        match self {
+            EntryHasher::None => EntryChecksum::None,
+            EntryHasher::CRC32(c) => EntryChecksum::CRC32(c),
+            EntryHasher::XXH64(h) => EntryChecksum::XXH64(h.digest())
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Archive Errors
+
+use std::io::{Error as IOError, ErrorKind};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::error::Error;
+use super::EntryChecksum;
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    IO(IOError),
+    /// The file does not start with any of known signatures
+    SignatureMismatch([u8; 4]),
+    /// Unknown compression method tag found in an entry
+    UnsupportedCompressionMethod(u8),
+    /// Unknown checksum method tag found in an entry
+    UnsupportedChecksumMethod(u8),
+    /// Checksum recorded in the header does not match the body
+    CRCMismatch { recorded: u32, input: u32 },
+    /// Checksum recorded for the entry does not match its stored bytes
+    EntryChecksumMismatch { name: String, recorded: EntryChecksum, input: EntryChecksum },
+    /// The entry table ended before all entries were read
+    TruncatedEntryTable,
+    /// No entry has the name
+    MissingEntry(String),
+    /// Borrowing entries requires the archive to be mapped(`ArchiveRead::map_file`) or on memory
+    NotOnMemory,
+    /// Whole-body compressed archives cannot be read without decompressing(`ArchiveView`)
+    CompressedBody,
+    /// The archive is signed(or encrypted) and must be opened with keys(`ArchiveRead::open_sealed`)
+    KeyRequired,
+    /// The archive is not signed although keys are configured
+    Unauthenticated,
+    /// The signature(or the authentication tag of encryption) does not verify against the key
+    AuthenticationFailed
+}
+pub type Result<T> = ::std::result::Result<T, ArchiveError>;
+
+impl From<IOError> for ArchiveError {
+    fn from(e: IOError) -> Self { ArchiveError::IO(e) }
+}
+/// for reporting through `std::io` based interfaces(e.g. `AssetLoader`)
+impl From<ArchiveError> for IOError {
+    fn from(e: ArchiveError) -> Self {
+        match e {
+            ArchiveError::IO(e) => e,
+            e @ ArchiveError::MissingEntry(_) => IOError::new(ErrorKind::NotFound, e),
+            e => IOError::new(ErrorKind::InvalidData, e)
+        }
+    }
+}
+impl Display for ArchiveError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match *self {
+            ArchiveError::IO(ref e) => write!(fmt, "IO Error: {}", e),
+            ArchiveError::SignatureMismatch(ref s) => write!(fmt, "Signature Mismatch: {:?}", String::from_utf8_lossy(s)),
+            ArchiveError::UnsupportedCompressionMethod(t) => write!(fmt, "Unsupported Compression method: {}", t),
+            ArchiveError::UnsupportedChecksumMethod(t) => write!(fmt, "Unsupported Checksum method: {}", t),
+            ArchiveError::CRCMismatch { recorded, input } =>
+                write!(fmt, "Checking Integrity Failed: Mismatching CRC-32: recorded=0x{:08x} input=0x{:08x}", recorded, input),
+            ArchiveError::EntryChecksumMismatch { ref name, ref recorded, ref input } =>
+                write!(fmt, "Corrupted Entry {}: Mismatching Checksum: recorded={} input={}", name, recorded, input),
+            ArchiveError::TruncatedEntryTable => write!(fmt, "Truncated Entry Table"),
+            ArchiveError::MissingEntry(ref n) => write!(fmt, "Entry not found in archive: {}", n),
+            ArchiveError::NotOnMemory => write!(fmt, "Archive is neither mapped nor on memory"),
+            ArchiveError::CompressedBody => write!(fmt, "Archive body is compressed as a whole"),
+            ArchiveError::KeyRequired => write!(fmt, "Archive is sealed: a verifying(or decryption) key is required"),
+            ArchiveError::Unauthenticated => write!(fmt, "Archive is not signed"),
+            ArchiveError::AuthenticationFailed => write!(fmt, "Authentication Failed: the archive is modified or the key does not match")
+        }
+    }
+}
+impl Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self { ArchiveError::IO(ref e) => Some(e), _ => None }
+    }
+}
@@ -0,0 +1,611 @@
+//! Peridot Archive
+
+extern crate peridot_serialization_utils;
+#[macro_use] extern crate peridot_serialization_derive;
+extern crate crc; extern crate lz4; extern crate libflate; extern crate zstd; extern crate xxhash_rust;
+extern crate memmap;
+#[cfg(feature = "security")] extern crate ed25519_dalek;
+#[cfg(feature = "security")] extern crate hmac;
+#[cfg(feature = "security")] extern crate sha2;
+#[cfg(feature = "security")] extern crate chacha20poly1305;
+
+mod error; pub use self::error::*;
+mod stream; pub use self::stream::*;
+mod naming; pub use self::naming::*;
+mod checksum; pub use self::checksum::*;
+mod mapped; pub use self::mapped::*;
+mod set; pub use self::set::*;
+mod view; pub use self::view::*;
+#[cfg(feature = "security")] mod sealed;
+#[cfg(feature = "security")] pub use self::sealed::*;
+
+use peridot_serialization_utils::*;
+use std::io::prelude::{Write, Read, BufRead};
+use std::io::{Result as IOResult, Error as IOError, ErrorKind};
+use std::io::{SeekFrom, Seek, BufReader};
+use std::fs::File;
+use std::collections::BTreeMap;
+use libflate::deflate as zlib;
+use libflate::lz77::DefaultLz77Encoder;
+use crc::crc32;
+use std::path::Path;
+
+/// 各エントリの格納位置と格納方法。`compression`と`checksum`、`tombstone`はランダムアクセス形式(`parr`)でのみ意味を持つ
+#[derive(Debug)]
+pub struct AssetEntryHeadingPair {
+    pub byte_length: u64, pub relative_offset: u64, pub compression: CompressionMethod, pub checksum: EntryChecksum,
+    /// The entry is deleted(patch archives hide the entry of underlying archives with this)
+    pub tombstone: bool
+}
+/// 圧縮方式の代わりに記録し、削除済みエントリを表す
+const TOMBSTONE_TAG: u8 = 0xff;
+/// エントリテーブルに書かれる見出し(byte_length, relative_offset)
+#[derive(PeridotSerialize, PeridotDeserialize)]
+struct HeadingLayout { byte_length: U64LE, relative_offset: U64LE }
+/// ランダムアクセス形式の見出し。圧縮方式(墓標ならTOMBSTONE_TAG)、展開後のサイズ、チェックサム方式、チェックサムが続く
+#[derive(PeridotSerialize, PeridotDeserialize)]
+struct RandomAccessHeadingLayout {
+    heading: HeadingLayout, compression: u8, uncompressed_bytes: U64LE, checksum_method: u8, checksum: U64LE
+}
+impl AssetEntryHeadingPair {
+    fn layout(&self) -> HeadingLayout {
+        HeadingLayout { byte_length: U64LE(self.byte_length), relative_offset: U64LE(self.relative_offset) }
+    }
+    fn write<W: Write>(&self, writer: &mut W) -> IOResult<usize> { self.layout().serialize(writer) }
+    fn read<R: BufRead>(reader: &mut R) -> IOResult<Self> {
+        let HeadingLayout { byte_length: U64LE(byte_length), relative_offset: U64LE(relative_offset) } =
+            HeadingLayout::deserialize(reader)?;
+        return Ok(AssetEntryHeadingPair {
+            byte_length, relative_offset, compression: CompressionMethod::None, checksum: EntryChecksum::None,
+            tombstone: false
+        });
+    }
+    fn write_random_access<W: Write>(&self, writer: &mut W) -> IOResult<usize> {
+        RandomAccessHeadingLayout {
+            heading: self.layout(),
+            compression: if self.tombstone { TOMBSTONE_TAG } else { self.compression.tag() },
+            uncompressed_bytes: U64LE(self.compression.uncompressed_bytes().unwrap_or(self.byte_length)),
+            checksum_method: self.checksum.tag(), checksum: U64LE(self.checksum.value())
+        }.serialize(writer)
+    }
+    fn read_random_access<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let layout = RandomAccessHeadingLayout::deserialize(reader).map_err(truncated_as_table_error)?;
+        let HeadingLayout { byte_length: U64LE(byte_length), relative_offset: U64LE(relative_offset) } = layout.heading;
+        let tombstone = layout.compression == TOMBSTONE_TAG;
+        let compression = if tombstone { CompressionMethod::None } else {
+            CompressionMethod::from_tag(layout.compression, layout.uncompressed_bytes.0)
+                .ok_or(ArchiveError::UnsupportedCompressionMethod(layout.compression))?
+        };
+        let checksum = EntryChecksum::from_tag(layout.checksum_method, layout.checksum.0)
+            .ok_or(ArchiveError::UnsupportedChecksumMethod(layout.checksum_method))?;
+        return Ok(AssetEntryHeadingPair { byte_length, relative_offset, compression, checksum, tombstone });
+    }
+}
+fn read_asset_entries<R: BufRead>(reader: &mut R, random_access: bool) -> Result<BTreeMap<String, AssetEntryHeadingPair>> {
+    let VariableUInt64(count) = VariableUInt64::read(reader).map_err(truncated_as_table_error)?;
+    let mut elements = BTreeMap::new();
+    for _ in 0 .. count {
+        let heading = if random_access { AssetEntryHeadingPair::read_random_access(reader)? }
+            else { AssetEntryHeadingPair::read(reader).map_err(truncated_as_table_error)? };
+        let PascalString(id_ref) = PascalString::read(reader).map_err(truncated_as_table_error)?;
+        elements.insert(id_ref, heading);
+    }
+    return Ok(elements);
+}
+fn truncated_as_table_error(e: IOError) -> ArchiveError {
+    if e.kind() == ErrorKind::UnexpectedEof { ArchiveError::TruncatedEntryTable } else { e.into() }
+}
+
+/// 展開後のサイズが値として入る。圧縮指定時には無視されるので適当な値を指定する。
+/// 圧縮レベルは書き込み側で別に指定する(`ArchiveWrite::with_compression_level`など)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompressionMethod {
+    None,
+    /// Deflate(zlib) by libflate, which has no compression levels of its own.
+    /// Levels map to: 0 stored, 1-8 LZ77 window of `1 << (level + 6)` bytes(128B-16KiB) with fixed huffman codes for 1-3
+    /// and dynamic ones for 4-8, 9 the default encoder(32KiB window, dynamic huffman codes)
+    Zlib(u64),
+    Lz4(u64), Zstd(u64)
+}
+impl CompressionMethod {
+    fn tag(&self) -> u8 {
+        match *self {
+            CompressionMethod::None => 0, CompressionMethod::Zlib(_) => 1,
+            CompressionMethod::Lz4(_) => 2, CompressionMethod::Zstd(_) => 3
+        }
+    }
+    fn from_tag(tag: u8, uncompressed_bytes: u64) -> Option<Self> {
+        match tag {
+            0 => Some(CompressionMethod::None),
+            1 => Some(CompressionMethod::Zlib(uncompressed_bytes)),
+            2 => Some(CompressionMethod::Lz4(uncompressed_bytes)),
+            3 => Some(CompressionMethod::Zstd(uncompressed_bytes)),
+            _ => None
+        }
+    }
+    pub fn uncompressed_bytes(&self) -> Option<u64> {
+        match *self {
+            CompressionMethod::None => None,
+            CompressionMethod::Zlib(ub) | CompressionMethod::Lz4(ub) | CompressionMethod::Zstd(ub) => Some(ub)
+        }
+    }
+    /// 同じ方式で展開後のサイズだけ差し替えたもの
+    fn with_uncompressed_bytes(&self, ub: u64) -> Self {
+        match *self {
+            CompressionMethod::None => CompressionMethod::None,
+            CompressionMethod::Zlib(_) => CompressionMethod::Zlib(ub),
+            CompressionMethod::Lz4(_) => CompressionMethod::Lz4(ub),
+            CompressionMethod::Zstd(_) => CompressionMethod::Zstd(ub)
+        }
+    }
+
+    /// Starts compressing into the sink.
+    /// level: zstd 1-22(11 if None), lz4 0-12(3 or higher for HC),
+    /// zlib 0-9(9 if None; see `CompressionMethod::Zlib` for the mapping).
+    /// The dictionary is used only by zstd
+    fn encoder<W: Write>(&self, sink: W, level: Option<u32>, dictionary: Option<&[u8]>) -> IOResult<Encoder<W>> {
+        match *self {
+            CompressionMethod::None => Ok(Encoder::None(sink)),
+            CompressionMethod::Zlib(_) => {
+                let options = match level {
+                    Some(0) => zlib::EncodeOptions::new().no_compression(),
+                    Some(l @ 1 ..= 8) => {
+                        let options = zlib::EncodeOptions::with_lz77(DefaultLz77Encoder::with_window_size(1 << (l + 6)));
+                        if l <= 3 { options.fixed_huffman_codes() } else { options }
+                    },
+                    _ => zlib::EncodeOptions::new()
+                };
+                Ok(Encoder::Zlib(zlib::Encoder::with_options(sink, options)))
+            },
+            CompressionMethod::Lz4(_) => lz4::EncoderBuilder::new().level(level.unwrap_or(0)).build(sink).map(Encoder::Lz4),
+            CompressionMethod::Zstd(_) =>
+                zstd::Encoder::with_dictionary(sink, level.unwrap_or(11) as _, dictionary.unwrap_or(&[])).map(Encoder::Zstd)
+        }
+    }
+    fn compress(&self, mut bytes: &[u8], level: Option<u32>, dictionary: Option<&[u8]>) -> IOResult<Vec<u8>> {
+        let mut e = self.encoder(Vec::new(), level, dictionary)?;
+        copy_chunked(&mut bytes, &mut e)?; e.finish()
+    }
+    /// Compresses the content of an entry to be added by `ArchiveStreamWrite::add_raw`(e.g. on worker threads).
+    /// return -> (stored bytes, the method with the uncompressed size)
+    pub fn compress_entry(&self, content: &[u8], level: Option<u32>, dictionary: Option<&[u8]>)
+            -> IOResult<(Vec<u8>, CompressionMethod)> {
+        let stored = self.compress(content, level, dictionary)?;
+        return Ok((stored, self.with_uncompressed_bytes(content.len() as _)));
+    }
+    fn decompress(&self, bytes: Vec<u8>, dictionary: Option<&[u8]>) -> IOResult<Vec<u8>> {
+        if *self == CompressionMethod::None { return Ok(bytes); }
+        return self.decompress_slice(&bytes, dictionary);
+    }
+    fn decompress_slice(&self, bytes: &[u8], dictionary: Option<&[u8]>) -> IOResult<Vec<u8>> {
+        match *self {
+            CompressionMethod::None => Ok(bytes.to_owned()),
+            CompressionMethod::Zlib(ub) => read_decompressed(zlib::Decoder::new(bytes), ub),
+            CompressionMethod::Lz4(ub) => read_decompressed(lz4::Decoder::new(bytes)?, ub),
+            CompressionMethod::Zstd(ub) => read_decompressed(zstd::Decoder::with_dictionary(bytes, dictionary.unwrap_or(&[]))?, ub)
+        }
+    }
+}
+/// Copies in chunks of the fixed size regardless of how the reader returns.
+/// Some encoders' output depends on how the input is split, so streamed and on-memory contents are compressed identically
+fn copy_chunked<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> IOResult<u64> {
+    let mut buf = vec![0u8; 64 << 10];
+    let mut copied = 0;
+    loop {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e)
+            }
+        }
+        if filled == 0 { return Ok(copied); }
+        writer.write_all(&buf[..filled])?;
+        copied += filled as u64;
+    }
+}
+/// Decompresses whole stream. Fails if the output exceeds the size recorded in the archive(e.g. decompression bombs)
+fn read_decompressed<R: Read>(decoder: R, uncompressed_bytes: u64) -> IOResult<Vec<u8>> {
+    let mut sink = Vec::with_capacity(capacity_hint(uncompressed_bytes));
+    decoder.take(uncompressed_bytes.saturating_add(1)).read_to_end(&mut sink)?;
+    if sink.len() as u64 > uncompressed_bytes {
+        return Err(IOError::new(ErrorKind::InvalidData, "Decompressed data exceeds the recorded size"));
+    }
+    return Ok(sink);
+}
+/// 各方式の圧縮ストリーム
+enum Encoder<W: Write> { None(W), Zlib(zlib::Encoder<W>), Lz4(lz4::Encoder<W>), Zstd(zstd::Encoder<W>) }
+impl<W: Write> Encoder<W> {
+    fn finish(self) -> IOResult<W> {
+        match self {
+            Encoder::None(w) => Ok(w),
+            Encoder::Zlib(e) => e.finish().into_result(),
+            Encoder::Lz4(e) => { let (w, r) = e.finish(); r.map(move |_| w) },
+            Encoder::Zstd(e) => e.finish()
+        }
+    }
+}
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        match *self {
+            Encoder::None(ref mut w) => w.write(buf), Encoder::Zlib(ref mut e) => e.write(buf),
+            Encoder::Lz4(ref mut e) => e.write(buf), Encoder::Zstd(ref mut e) => e.write(buf)
+        }
+    }
+    fn flush(&mut self) -> IOResult<()> {
+        match *self {
+            Encoder::None(ref mut w) => w.flush(), Encoder::Zlib(ref mut e) => e.flush(),
+            Encoder::Lz4(ref mut e) => e.flush(), Encoder::Zstd(ref mut e) => e.flush()
+        }
+    }
+}
+/// Trains a zstd dictionary from sample contents(e.g. all files to be archived). Fails if samples are too few
+pub fn train_zstd_dictionary<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> IOResult<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_size)
+}
+/// アーカイブ本体の構造
+#[derive(Debug)]
+enum ArchiveLayout {
+    /// エントリテーブルと内容全体をまとめて圧縮したもの(`par `/`pard`/`parz`/`par1`)
+    WholeBody(CompressionMethod),
+    /// エントリごとに圧縮された内容(と辞書)の後ろにエントリテーブルを置いたもの(`parr`)。値は本体先頭からのテーブルの位置
+    RandomAccess(u64)
+}
+fn read_file_header<R: BufRead>(reader: &mut R) -> Result<(ArchiveLayout, u32)> {
+    let mut signature = [0u8; 4];
+    reader.read_exact(&mut signature[..]).map(drop)?;
+    let comp = match &signature {
+        b"par " => CompressionMethod::None,
+        b"pard" => U64LE::read(reader).map(|U64LE(v)| CompressionMethod::Zlib(v))?,
+        b"parz" => U64LE::read(reader).map(|U64LE(v)| CompressionMethod::Lz4(v))?,
+        b"par1" => U64LE::read(reader).map(|U64LE(v)| CompressionMethod::Zstd(v))?,
+        b"parr" => {
+            let (U32LE(crc32), U64LE(table_offset)) = (U32LE::read(reader)?, U64LE::read(reader)?);
+            return Ok((ArchiveLayout::RandomAccess(table_offset), crc32));
+        },
+        // 署名/暗号化されたアーカイブは鍵を指定して開く(`security` feature)
+        b"pars" => return Err(ArchiveError::KeyRequired),
+        _ => return Err(ArchiveError::SignatureMismatch(signature))
+    };
+    let U32LE(crc32) = U32LE::read(reader)?;
+    return Ok((ArchiveLayout::WholeBody(comp), crc32));
+}
+
+/// return -> written bytes(raw)
+fn write_asset_entries<W: Write>(writer: &mut W, entries: &BTreeMap<String, AssetEntryHeadingPair>, random_access: bool)
+        -> IOResult<usize> {
+    let mut written_bytes = VariableUInt64(entries.len() as _).write(writer)?;
+    for (n, h) in entries {
+        let w1 = if random_access { h.write_random_access(writer)? } else { h.write(writer)? };
+        written_bytes += PascalStr(n).write(writer).map(move |w2| w1 + w2)?;
+    }
+    return Ok(written_bytes);
+}
+/// ランダムアクセス形式のエントリテーブルの末尾に置く、zstd辞書の位置(byte_length, relative_offset)。辞書がなければ長さ0
+fn write_dictionary_heading<W: Write>(writer: &mut W, relative_offset: u64, byte_length: u64) -> IOResult<usize> {
+    AssetEntryHeadingPair {
+        byte_length, relative_offset, compression: CompressionMethod::None, checksum: EntryChecksum::None, tombstone: false
+    }.write(writer)
+}
+
+use std::io::Cursor;
+/// The entry table is sorted by name and contents are laid out in the order added,
+/// so the same sequence of `add` always produces the same bytes.
+pub struct ArchiveWrite {
+    comp: CompressionMethod, random_access: bool, checksum: ChecksumMethod, level: Option<u32>, dictionary: Option<Vec<u8>>,
+    entries: BTreeMap<String, AssetEntryHeadingPair>, content: Vec<u8>
+}
+impl ArchiveWrite {
+    /// エントリテーブルと内容全体をまとめて圧縮するアーカイブ
+    pub fn new(comp: CompressionMethod) -> Self {
+        ArchiveWrite {
+            comp, random_access: false, checksum: ChecksumMethod::None, level: None, dictionary: None,
+            entries: BTreeMap::new(), content: Vec::new()
+        }
+    }
+    /// エントリごとに圧縮し、1エントリだけを読み出せるアーカイブ。各エントリにはCRC-32が付く
+    pub fn random_access(comp: CompressionMethod) -> Self {
+        ArchiveWrite {
+            comp, random_access: true, checksum: ChecksumMethod::CRC32, level: None, dictionary: None,
+            entries: BTreeMap::new(), content: Vec::new()
+        }
+    }
+    /// Sets the algorithm of per-entry checksums. Ignored unless the archive is random-access
+    pub fn with_checksum(mut self, method: ChecksumMethod) -> Self {
+        if self.random_access { self.checksum = method; } return self;
+    }
+    /// Sets the compression level(see `CompressionMethod` for ranges). Must be set before adding entries
+    pub fn with_compression_level(mut self, level: u32) -> Self { self.level = Some(level); return self; }
+    /// Compresses entries with the zstd dictionary and embeds it in the archive.
+    /// Ignored unless the archive is random-access. Must be set before adding entries
+    pub fn with_dictionary(mut self, dictionary: Vec<u8>) -> Self {
+        if self.random_access { self.dictionary = Some(dictionary); } return self;
+    }
+    /// return -> false if the name has already been added
+    pub fn add(&mut self, name: String, content: Vec<u8>) -> IOResult<bool> {
+        if self.entries.contains_key(&name) { return Ok(false); }
+        let relative_offset = self.content.len() as u64;
+        let (compression, checksum) = if self.random_access {
+            let stored = self.comp.compress(&content, self.level, self.dictionary.as_ref().map(|d| &d[..]))?;
+            let checksum = self.checksum.checksum(&stored);
+            self.content.extend(stored);
+            (self.comp.with_uncompressed_bytes(content.len() as _), checksum)
+        }
+        else {
+            self.content.extend(content); (CompressionMethod::None, EntryChecksum::None)
+        };
+        self.entries.insert(name, AssetEntryHeadingPair {
+            relative_offset, byte_length: self.content.len() as u64 - relative_offset, compression, checksum, tombstone: false
+        });
+        return Ok(true);
+    }
+    /// return -> written bytes(raw)
+    fn write_asset_entries<W: Write>(&self, writer: &mut W) -> IOResult<usize> {
+        write_asset_entries(writer, &self.entries, self.random_access)
+    }
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        if self.random_access {
+            let dictionary = self.dictionary.as_ref().map_or(&[][..], |d| &d[..]);
+            let mut body = Cursor::new(Vec::new());
+            body.write_all(&self.content[..])?; body.write_all(dictionary)?;
+            self.write_asset_entries(&mut body)?;
+            write_dictionary_heading(&mut body, self.content.len() as _, dictionary.len() as _)?;
+            let body = body.into_inner();
+            let checksum = crc32::checksum_ieee(&body[..]);
+            let table_offset = (self.content.len() + dictionary.len()) as u64;
+
+            writer.write_all(b"parr")?;
+            U32LE(checksum).write(writer)?;
+            U64LE(table_offset).write(writer)?;
+            return writer.write_all(&body[..]).map_err(From::from);
+        }
+
+        let signature = match self.comp {
+            CompressionMethod::None => {
+                let mut body = Cursor::new(Vec::new());
+                self.write_asset_entries(&mut body)?; body.write_all(&self.content[..])?;
+
+                return Self::write_common(writer, b"par ", None, &body.into_inner()[..]).map_err(From::from);
+            },
+            CompressionMethod::Zlib(_) => b"pard", CompressionMethod::Lz4(_) => b"parz", CompressionMethod::Zstd(_) => b"par1"
+        };
+        let mut body = self.comp.encoder(Vec::new(), self.level, None)?;
+        let uncompressed_bytes = self.write_asset_entries(&mut body)
+            .and_then(|wa| body.write_all(&self.content[..]).map(move |_| wa + self.content.len()))? as u64;
+
+        Self::write_common(writer, signature, Some(uncompressed_bytes), &body.finish()?[..]).map_err(From::from)
+    }
+    fn write_common<W: Write>(writer: &mut W, signature: &[u8], uncompressed_bytes: Option<u64>, body: &[u8])
+            -> IOResult<()> {
+        let checksum = crc32::checksum_ieee(body);
+        writer.write_all(signature)?;
+        if let Some(ub) = uncompressed_bytes { U64LE(ub).write(writer)?; }
+        U32LE(checksum).write(writer)?;
+        writer.write_all(body)
+    }
+}
+
+/// `Mapped`はヘッダの直後(本体の先頭)を指した状態で渡す
+enum WhereArchive { OnMemory(Vec<u8>), FromIO(BufReader<File>), Mapped(Cursor<SharedMap>) }
+impl WhereArchive {
+    pub fn on_memory(&mut self) -> IOResult<&[u8]> {
+        let replace_buf = if let WhereArchive::FromIO(ref mut r) = self {
+            let mut buf = Vec::new();
+            r.read_to_end(&mut buf)?; Some(buf)
+        }
+        else { None };
+        if let Some(b) = replace_buf { *self = WhereArchive::OnMemory(b); }
+        match self {
+            WhereArchive::OnMemory(ref b) => Ok(b),
+            WhereArchive::Mapped(ref c) => Ok(&c.get_ref().as_ref()[c.position() as usize..]),
+            _ => unreachable!()
+        }
+    }
+}
+enum EitherArchiveReader { OnMemory(Cursor<Vec<u8>>), FromIO(BufReader<File>), Mapped(Cursor<SharedMap>) }
+impl EitherArchiveReader {
+    pub fn new(a: WhereArchive) -> Self {
+        match a {
+            WhereArchive::FromIO(r) => EitherArchiveReader::FromIO(r),
+            WhereArchive::OnMemory(b) => EitherArchiveReader::OnMemory(Cursor::new(b)),
+            WhereArchive::Mapped(c) => EitherArchiveReader::Mapped(c)
+        }
+    }
+}
+impl Read for EitherArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        match self {
+            EitherArchiveReader::FromIO(ref mut r) => r.read(buf),
+            EitherArchiveReader::OnMemory(ref mut c) => c.read(buf),
+            EitherArchiveReader::Mapped(ref mut c) => c.read(buf)
+        }
+    }
+}
+impl BufRead for EitherArchiveReader {
+    fn fill_buf(&mut self) -> IOResult<&[u8]> {
+        match self {
+            EitherArchiveReader::FromIO(ref mut r) => r.fill_buf(),
+            EitherArchiveReader::OnMemory(ref mut c) => c.fill_buf(),
+            EitherArchiveReader::Mapped(ref mut c) => c.fill_buf()
+        }
+    }
+    fn consume(&mut self, amt: usize) {
+        match self {
+            EitherArchiveReader::FromIO(ref mut r) => r.consume(amt),
+            EitherArchiveReader::OnMemory(ref mut c) => c.consume(amt),
+            EitherArchiveReader::Mapped(ref mut c) => c.consume(amt)
+        }
+    }
+}
+impl Seek for EitherArchiveReader {
+    fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
+        match self {
+            EitherArchiveReader::FromIO(ref mut r) => r.seek(pos),
+            EitherArchiveReader::OnMemory(ref mut c) => c.seek(pos),
+            EitherArchiveReader::Mapped(ref mut c) => c.seek(pos)
+        }
+    }
+}
+
+pub struct ArchiveRead {
+    entries: BTreeMap<String, AssetEntryHeadingPair>, content: EitherArchiveReader,
+    content_baseptr: u64, compression: CompressionMethod, random_access: bool, verify_entries: bool,
+    dictionary: Option<Vec<u8>>, crc: u32
+}
+impl ArchiveRead {
+    pub fn from_file<P: AsRef<Path>>(path: P, check_integrity: bool) -> Result<Self> {
+        let mut fi = File::open(path).map(BufReader::new)?;
+        let (layout, crc) = read_file_header(&mut fi)?;
+        return Self::from_body(layout, crc, WhereArchive::FromIO(fi), check_integrity);
+    }
+    /// Reads the archive file on memory(e.g. downloaded from content servers)
+    pub fn from_bytes(bytes: Vec<u8>, check_integrity: bool) -> Result<Self> {
+        let mut archive = Cursor::new(bytes);
+        let (layout, crc) = read_file_header(&mut archive)?;
+        let header_length = archive.position() as usize;
+        let body = archive.into_inner().split_off(header_length);
+        return Self::from_body(layout, crc, WhereArchive::OnMemory(body), check_integrity);
+    }
+    fn from_body(layout: ArchiveLayout, crc: u32, mut body: WhereArchive, check_integrity: bool) -> Result<Self> {
+        if check_integrity {
+            let input_crc = crc32::checksum_ieee(&body.on_memory()?[..]);
+            if input_crc != crc { return Err(ArchiveError::CRCMismatch { recorded: crc, input: input_crc }); }
+        }
+        if let ArchiveLayout::RandomAccess(table_offset) = layout {
+            // 内容は必要になったエントリだけ読み出す
+            let mut areader = EitherArchiveReader::new(body);
+            let content_baseptr = areader.seek(SeekFrom::Current(0))?;
+            areader.seek(SeekFrom::Start(content_position(content_baseptr, table_offset)?))?;
+            let entries = read_asset_entries(&mut areader, true)?;
+            let dictionary_heading = AssetEntryHeadingPair::read(&mut areader).map_err(truncated_as_table_error)?;
+            let dictionary = if dictionary_heading.byte_length == 0 { None } else {
+                areader.seek(SeekFrom::Start(content_position(content_baseptr, dictionary_heading.relative_offset)?))?;
+                Some(read_bytes(&mut areader, dictionary_heading.byte_length)?)
+            };
+
+            return Ok(ArchiveRead {
+                entries, content: areader, content_baseptr, compression: CompressionMethod::None, random_access: true,
+                verify_entries: false, dictionary, crc
+            });
+        }
+        match layout {
+            ArchiveLayout::WholeBody(CompressionMethod::Lz4(ub)) => {
+                body = WhereArchive::OnMemory(read_decompressed(lz4::Decoder::new(EitherArchiveReader::new(body))?, ub)?);
+            },
+            ArchiveLayout::WholeBody(CompressionMethod::Zlib(ub)) => {
+                body = WhereArchive::OnMemory(read_decompressed(zlib::Decoder::new(EitherArchiveReader::new(body)), ub)?);
+            },
+            ArchiveLayout::WholeBody(CompressionMethod::Zstd(ub)) => {
+                body = WhereArchive::OnMemory(read_decompressed(zstd::Decoder::new(EitherArchiveReader::new(body))?, ub)?);
+            },
+            _ => ()
+        }
+        let mut areader = EitherArchiveReader::new(body);
+        let entries = read_asset_entries(&mut areader, false)?;
+        let content_baseptr = areader.seek(SeekFrom::Current(0))?;
+        let compression = match layout { ArchiveLayout::WholeBody(c) => c, _ => unreachable!() };
+
+        return Ok(ArchiveRead {
+            entries, content: areader, content_baseptr, compression, random_access: false, verify_entries: false,
+            dictionary: None, crc
+        });
+    }
+
+    /// true if the archive has `parr` layout(entries are compressed individually)
+    pub fn is_random_access(&self) -> bool { self.random_access }
+    /// Signature of the archive file
+    pub fn signature(&self) -> &'static [u8; 4] {
+        if self.random_access { return b"parr"; }
+        match self.compression {
+            CompressionMethod::None => b"par ", CompressionMethod::Zlib(_) => b"pard",
+            CompressionMethod::Lz4(_) => b"parz", CompressionMethod::Zstd(_) => b"par1"
+        }
+    }
+    /// CRC-32 of the body recorded in the header
+    pub fn recorded_crc32(&self) -> u32 { self.crc }
+    /// Compression method applied to the whole body. `None` for random-access archives
+    pub fn compression_method(&self) -> &CompressionMethod { &self.compression }
+    /// Compression method applied to the entry. `None` for entries in whole-body compressed archives
+    pub fn entry_compression_method(&self, path: &str) -> Option<&CompressionMethod> {
+        self.entries.get(path).map(|e| &e.compression)
+    }
+
+    /// Where and how the entry is stored. Offsets are relative to the start of contents
+    pub fn entry_heading(&self, path: &str) -> Option<&AssetEntryHeadingPair> { self.entries.get(path) }
+    /// The zstd dictionary embedded in the archive
+    pub fn dictionary(&self) -> Option<&[u8]> { self.dictionary.as_ref().map(|d| &d[..]) }
+    /// Checksum recorded for the entry. `EntryChecksum::None` for entries in whole-body compressed archives
+    pub fn entry_checksum(&self, path: &str) -> Option<&EntryChecksum> {
+        self.entries.get(path).map(|e| &e.checksum)
+    }
+    /// Validates per-entry checksums on every `read_bin`/`read_raw`(disabled by default)
+    pub fn set_entry_verification(&mut self, enabled: bool) { self.verify_entries = enabled; }
+
+    /// true if the archive has the entry(and it is not deleted by a tombstone)
+    pub fn contains(&self, path: &str) -> bool { self.entries.get(path).map_or(false, |e| !e.tombstone) }
+    /// true if the archive has a tombstone of the entry
+    pub fn is_tombstone(&self, path: &str) -> bool { self.entries.get(path).map_or(false, |e| e.tombstone) }
+    fn live_entry(&self, path: &str) -> Result<&AssetEntryHeadingPair> {
+        self.entries.get(path).filter(|e| !e.tombstone).ok_or_else(|| ArchiveError::MissingEntry(path.to_owned()))
+    }
+    pub fn read_bin(&mut self, path: &str) -> Result<Vec<u8>> {
+        let (stored, compression) = self.read_raw(path)?;
+        return compression.decompress(stored, self.dictionary()).map_err(From::from);
+    }
+    /// Reads an entry as stored(without decompressing). return -> (stored bytes, compression method of the entry)
+    pub fn read_raw(&mut self, path: &str) -> Result<(Vec<u8>, CompressionMethod)> {
+        let (relative_offset, byte_length, checksum, compression) = {
+            let e = self.live_entry(path)?;
+            (e.relative_offset, e.byte_length, e.checksum, e.compression.clone())
+        };
+        self.content.seek(SeekFrom::Start(content_position(self.content_baseptr, relative_offset)?))?;
+        let sink = read_bytes(&mut self.content, byte_length)?;
+        if self.verify_entries { check_entry_checksum(path, &checksum, &sink)?; }
+        return Ok((sink, compression));
+    }
+    /// Reads the entry and validates its checksum regardless of `set_entry_verification`.
+    /// return -> false if the entry has no checksum to be validated
+    pub fn verify_entry(&mut self, path: &str) -> Result<bool> {
+        let verify = self.verify_entries;
+        self.verify_entries = true;
+        let r = self.read_raw(path);
+        self.verify_entries = verify;
+        return r.map(|_| self.entries[path].checksum != EntryChecksum::None);
+    }
+    /// Names of entries(except tombstones) in order
+    pub fn entry_names(&self) -> ArchiveEntryIterator<'_> {
+        ArchiveEntryIterator(self.entries.iter(), false)
+    }
+    /// Names of entries deleted by tombstones in order
+    pub fn tombstones(&self) -> ArchiveEntryIterator<'_> {
+        ArchiveEntryIterator(self.entries.iter(), true)
+    }
+}
+/// 記録された位置が壊れていてもあふれないように
+fn content_position(content_baseptr: u64, relative_offset: u64) -> IOResult<u64> {
+    content_baseptr.checked_add(relative_offset)
+        .ok_or_else(|| IOError::new(ErrorKind::InvalidData, "Entry offset exceeds the archive"))
+}
+/// 記録された範囲が入力に収まっている場合だけ切り出す
+fn content_slice<'d>(whole: &'d [u8], start: u64, byte_length: u64, path: &str) -> IOResult<&'d [u8]> {
+    start.checked_add(byte_length).filter(|&end| end <= whole.len() as u64)
+        .map(|end| &whole[start as usize .. end as usize])
+        .ok_or_else(|| IOError::new(ErrorKind::UnexpectedEof, format!("Entry {} exceeds the archive", path)))
+}
+fn check_entry_checksum(path: &str, recorded: &EntryChecksum, stored: &[u8]) -> Result<()> {
+    let input = recorded.method().checksum(stored);
+    if input == *recorded { Ok(()) }
+    else { Err(ArchiveError::EntryChecksumMismatch { name: path.to_owned(), recorded: *recorded, input }) }
+}
+use std::collections::btree_map::Iter;
+/// 第2要素がtrueなら削除済みエントリだけ、falseならそれ以外を列挙する
+pub struct ArchiveEntryIterator<'a>(Iter<'a, String, AssetEntryHeadingPair>, bool);
+impl<'a> Iterator for ArchiveEntryIterator<'a> {
+    type Item = &'a str;
+    fn next(&mut self) -> Option<&'a str> {
+        let tombstones = self.1;
+        self.0.by_ref().find(|&(_, e)| e.tombstone == tombstones).map(|(n, _)| n.as_str())
+    }
+}
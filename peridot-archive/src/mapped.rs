@@ -0,0 +1,80 @@
+//! Memory-mapped Archive Reading
+
+use super::*;
+use memmap::Mmap;
+use std::sync::Arc;
+use std::borrow::Cow;
+use std::ops::{Deref, Range};
+
+/// Whole bytes of a memory-mapped archive file, shared by the reader and entries served from it
+#[derive(Clone)]
+pub struct SharedMap(Arc<Mmap>);
+impl SharedMap {
+    /// Maps the whole file(e.g. to be read by `ArchiveView`). The file must not be modified while mapped
+    pub fn open<P: AsRef<Path>>(path: P) -> IOResult<Self> {
+        let fi = File::open(path)?;
+        return Ok(SharedMap(Arc::new(unsafe { Mmap::map(&fi)? })));
+    }
+}
+impl AsRef<[u8]> for SharedMap {
+    fn as_ref(&self) -> &[u8] { &self.0[..] }
+}
+
+/// Content of an entry: a range of the mapped archive(no copies), or decompressed bytes
+pub enum EntryBytes { Mapped(SharedMap, Range<usize>), Owned(Vec<u8>) }
+impl AsRef<[u8]> for EntryBytes {
+    fn as_ref(&self) -> &[u8] {
+        match *self { EntryBytes::Mapped(ref m, ref r) => &m.as_ref()[r.clone()], EntryBytes::Owned(ref b) => b }
+    }
+}
+impl Deref for EntryBytes {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] { self.as_ref() }
+}
+
+impl ArchiveRead {
+    /// Maps the archive file on memory.
+    /// Entries of uncompressed(`par `) and random-access(`parr`) archives are read directly from the mapping,
+    /// and whole-body compressed archives are decompressed on memory as `from_file` does.
+    pub fn map_file<P: AsRef<Path>>(path: P, check_integrity: bool) -> Result<Self> {
+        // ファイルが書き換えられないことを前提とする
+        let mut body = Cursor::new(SharedMap::open(path)?);
+        let (layout, crc) = read_file_header(&mut body)?;
+        return Self::from_body(layout, crc, WhereArchive::Mapped(body), check_integrity);
+    }
+
+    /// Stored(possibly compressed) bytes of the entry, borrowed without copying.
+    /// Available when the archive is mapped by `map_file` or decompressed on memory
+    pub fn stored_slice(&self, path: &str) -> Result<&[u8]> {
+        let entry_pair = self.live_entry(path)?;
+        let whole: &[u8] = match self.content {
+            EitherArchiveReader::Mapped(ref c) => c.get_ref().as_ref(),
+            EitherArchiveReader::OnMemory(ref c) => c.get_ref(),
+            EitherArchiveReader::FromIO(_) => return Err(ArchiveError::NotOnMemory)
+        };
+        let start = content_position(self.content_baseptr, entry_pair.relative_offset)?;
+        let slice = content_slice(whole, start, entry_pair.byte_length, path)?;
+        if self.verify_entries { check_entry_checksum(path, &entry_pair.checksum, slice)?; }
+        return Ok(slice);
+    }
+    /// Content of the entry: borrowed for uncompressed entries, decompressed otherwise.
+    /// Available when the archive is mapped by `map_file` or decompressed on memory
+    pub fn read_slice(&self, path: &str) -> Result<Cow<'_, [u8]>> {
+        let stored = self.stored_slice(path)?;
+        return match self.entries[path].compression {
+            CompressionMethod::None => Ok(Cow::Borrowed(stored)),
+            ref c => c.decompress_slice(stored, self.dictionary()).map(Cow::Owned).map_err(From::from)
+        };
+    }
+    /// Same as `read_slice`, but the result shares the mapping and can outlive the borrow of the reader
+    pub fn read_shared(&self, path: &str) -> Result<EntryBytes> {
+        if let EitherArchiveReader::Mapped(ref c) = self.content {
+            if self.entries.get(path).map_or(false, |e| e.compression == CompressionMethod::None) {
+                let len = self.stored_slice(path)?.len();
+                let start = (self.content_baseptr + self.entries[path].relative_offset) as usize;
+                return Ok(EntryBytes::Mapped(c.get_ref().clone(), start .. start + len));
+            }
+        }
+        return self.read_slice(path).map(|b| EntryBytes::Owned(b.into_owned()));
+    }
+}
@@ -0,0 +1,45 @@
+//! Entry Naming: archives store asset paths in the form `AssetLoader::get(path, ext)` resolves
+
+use std::path::{Path, PathBuf, Component};
+
+/// Entry name for an asset: `shaders.pass` + `pvp` -> `shaders/pass.pvp`
+pub fn asset_entry_name(asset_path: &str, ext: &str) -> String {
+    let mut name = asset_path.replace(".", "/");
+    if !ext.is_empty() { name.push('.'); name.push_str(ext); }
+    return name;
+}
+/// Portable entry name for a file: relative to `base` and separated by `/` on any platform.
+/// None if the path is not under the base(or goes up with `..`)
+pub fn portable_entry_name<P: AsRef<Path>, B: AsRef<Path>>(path: P, base: B) -> Option<String> {
+    // `./a`と`a`、`.`と空のベースを同一視する。`\`区切りもどのプラットフォームでも区切りとして扱う
+    let normalize = |p: &Path| p.to_str().map(|s| Path::new(&s.replace('\\', "/")).components()
+        .filter(|c| *c != Component::CurDir).collect::<PathBuf>());
+    let (path, base) = (normalize(path.as_ref())?, normalize(base.as_ref())?);
+    let relative = path.strip_prefix(&base).ok()?;
+    let mut name = String::new();
+    for c in relative.components() {
+        match c {
+            Component::Normal(s) => {
+                if !name.is_empty() { name.push('/'); }
+                name.push_str(s.to_str()?);
+            },
+            Component::CurDir => (),
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None
+        }
+    }
+    return if name.is_empty() { None } else { Some(name) };
+}
+/// Maps an entry name onto a path under `base`. None if the name is absolute or escapes `base` via `..`
+pub fn entry_output_path(base: &Path, name: &str) -> Option<PathBuf> {
+    if name.starts_with('/') || name.starts_with('\\') { return None; }
+    let mut path = base.to_owned();
+    for c in name.split(|c| c == '/' || c == '\\') {
+        match c {
+            "" | "." => (),
+            // ドライブ指定(C:)もはじく
+            ".." => return None, c if c.contains(':') => return None,
+            c => path.push(c)
+        }
+    }
+    return Some(path);
+}
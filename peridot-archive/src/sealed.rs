@@ -0,0 +1,165 @@
+//! Signed/Encrypted Archives(`security` feature)
+//!
+//! A sealed archive(`pars`) wraps a whole archive file:
+//! `pars` | authentication method(1byte) | cipher(1byte) | reserved(2bytes) | nonce(12bytes) | payload length(u64) |
+//! payload(the archive, encrypted if cipher is not 0) | signature(HMAC-SHA256: 32bytes, Ed25519: 64bytes).
+//! The signature covers everything before it, so any modification(including of ciphertext) is detected before decrypting.
+
+use super::*;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, NewAead};
+use std::convert::TryFrom;
+
+const AUTH_HMAC_SHA256: u8 = 1;
+const AUTH_ED25519: u8 = 2;
+const CIPHER_NONE: u8 = 0;
+const CIPHER_CHACHA20_POLY1305: u8 = 1;
+const HEADER_LENGTH: usize = 4 + 1 + 1 + 2 + 12 + 8;
+/// HMAC-SHA256の出力と同じ長さ未満の鍵は強度が足りない
+const MIN_HMAC_KEY_LENGTH: usize = 32;
+/// 暗号化の鍵をそのままnonceの生成に使わないよう、この値で別の鍵を導出する
+const NONCE_KEY_LABEL: &[u8] = b"peridot-archive sealed nonce v1";
+
+/// Keys to seal archives at build time
+pub struct SealingKeys { signer: ArchiveSigner, encryption_key: Option<[u8; 32]> }
+enum ArchiveSigner { Hmac(Vec<u8>), Ed25519(Keypair) }
+impl SealingKeys {
+    /// Signs with the Ed25519 secret key(32 bytes)
+    pub fn ed25519(secret_key: &[u8]) -> Result<Self> {
+        let secret = SecretKey::from_bytes(secret_key).map_err(invalid_key)?;
+        let public = PublicKey::from(&secret);
+        return Ok(SealingKeys { signer: ArchiveSigner::Ed25519(Keypair { secret, public }), encryption_key: None });
+    }
+    /// Authenticates with HMAC-SHA256 using the key of 32 bytes or longer. Anyone who has the key can also forge archives
+    pub fn hmac(key: Vec<u8>) -> Result<Self> {
+        check_hmac_key(&key)?;
+        return Ok(SealingKeys { signer: ArchiveSigner::Hmac(key), encryption_key: None });
+    }
+    /// Encrypts the archive with ChaCha20-Poly1305(256bit key)
+    pub fn with_encryption_key(mut self, key: [u8; 32]) -> Self { self.encryption_key = Some(key); return self; }
+    /// The Ed25519 public key to be configured in readers(`ArchiveKeys::ed25519`)
+    pub fn public_key(&self) -> Option<[u8; 32]> {
+        match self.signer { ArchiveSigner::Ed25519(ref k) => Some(k.public.to_bytes()), ArchiveSigner::Hmac(_) => None }
+    }
+
+    /// Writes the archive file(whole bytes written by `ArchiveWrite`/`ArchiveStreamWrite`) sealed
+    pub fn seal<W: Write>(&self, archive: &[u8], writer: &mut W) -> Result<()> {
+        let (cipher, nonce, payload) = match self.encryption_key {
+            Some(ref key) => {
+                // 同じ内容からは同じ出力になるよう、nonceは内容のHMACから作る(異なる内容でnonceが衝突しない)
+                let mut mac = Hmac::<Sha256>::new_from_slice(&nonce_key(key)).unwrap();
+                mac.update(archive);
+                let mut nonce = [0u8; 12];
+                nonce.copy_from_slice(&mac.finalize().into_bytes()[..12]);
+                let payload = ChaCha20Poly1305::new(&Key::from(*key)).encrypt(&Nonce::from(nonce), archive)
+                    .map_err(|_| IOError::new(ErrorKind::Other, "Encrypting the archive failed"))?;
+                (CIPHER_CHACHA20_POLY1305, nonce, payload)
+            },
+            None => (CIPHER_NONE, [0u8; 12], archive.to_owned())
+        };
+        let auth = match self.signer { ArchiveSigner::Hmac(_) => AUTH_HMAC_SHA256, ArchiveSigner::Ed25519(_) => AUTH_ED25519 };
+        let payload_length = payload.len() as u64;
+        let mut message = Vec::with_capacity(HEADER_LENGTH + payload.len());
+        message.extend_from_slice(b"pars"); message.extend_from_slice(&[auth, cipher, 0, 0]);
+        message.extend_from_slice(&nonce);
+        message.extend_from_slice(&U64LE(payload_length).to_bytes());
+        message.extend(payload);
+
+        let signature = match self.signer {
+            ArchiveSigner::Hmac(ref key) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+                mac.update(&message); mac.finalize().into_bytes().to_vec()
+            },
+            ArchiveSigner::Ed25519(ref k) => k.sign(&message).to_bytes().to_vec()
+        };
+        writer.write_all(&message)?; writer.write_all(&signature)?;
+        return Ok(());
+    }
+}
+
+/// Keys to open sealed archives. Archives not signed with the key are rejected
+pub struct ArchiveKeys { verifier: ArchiveVerifier, decryption_key: Option<[u8; 32]> }
+enum ArchiveVerifier { Hmac(Vec<u8>), Ed25519(PublicKey) }
+impl ArchiveKeys {
+    /// Verifies Ed25519 signatures with the public key(32 bytes)
+    pub fn ed25519(public_key: &[u8]) -> Result<Self> {
+        let k = PublicKey::from_bytes(public_key).map_err(invalid_key)?;
+        return Ok(ArchiveKeys { verifier: ArchiveVerifier::Ed25519(k), decryption_key: None });
+    }
+    /// Verifies HMAC-SHA256 with the shared key(32 bytes or longer, as `SealingKeys::hmac`)
+    pub fn hmac(key: Vec<u8>) -> Result<Self> {
+        check_hmac_key(&key)?;
+        return Ok(ArchiveKeys { verifier: ArchiveVerifier::Hmac(key), decryption_key: None });
+    }
+    /// Decrypts encrypted archives with the key
+    pub fn with_decryption_key(mut self, key: [u8; 32]) -> Self { self.decryption_key = Some(key); return self; }
+
+    /// Verifies the sealed archive. return -> the archive file inside(decrypted)
+    pub fn unseal(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if !sealed.starts_with(b"pars") { return Err(ArchiveError::Unauthenticated); }
+        if sealed.len() < HEADER_LENGTH { return Err(IOError::new(ErrorKind::UnexpectedEof, "Truncated sealed archive").into()); }
+        let (auth, cipher) = (sealed[4], sealed[5]);
+        let signature_length = match (auth, &self.verifier) {
+            (AUTH_HMAC_SHA256, &ArchiveVerifier::Hmac(_)) => 32,
+            (AUTH_ED25519, &ArchiveVerifier::Ed25519(_)) => 64,
+            _ => return Err(ArchiveError::AuthenticationFailed)
+        };
+        let mut payload_length = [0u8; 8];
+        payload_length.copy_from_slice(&sealed[HEADER_LENGTH - 8 .. HEADER_LENGTH]);
+        let U64LE(payload_length) = U64LE::from_bytes(payload_length);
+        if (sealed.len() - HEADER_LENGTH) as u64 != payload_length.saturating_add(signature_length as u64) {
+            return Err(ArchiveError::AuthenticationFailed);
+        }
+        let (message, signature) = sealed.split_at(sealed.len() - signature_length);
+        let verified = match self.verifier {
+            ArchiveVerifier::Hmac(ref key) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+                mac.update(message); mac.verify(signature).is_ok()
+            },
+            ArchiveVerifier::Ed25519(ref k) => Signature::try_from(signature).map_or(false, |s| k.verify(message, &s).is_ok())
+        };
+        if !verified { return Err(ArchiveError::AuthenticationFailed); }
+
+        let payload = &message[HEADER_LENGTH..];
+        return match cipher {
+            CIPHER_NONE => Ok(payload.to_owned()),
+            CIPHER_CHACHA20_POLY1305 => {
+                let key = self.decryption_key.ok_or(ArchiveError::KeyRequired)?;
+                let mut nonce = [0u8; 12]; nonce.copy_from_slice(&sealed[8..20]);
+                ChaCha20Poly1305::new(&Key::from(key)).decrypt(&Nonce::from(nonce), payload)
+                    .map_err(|_| ArchiveError::AuthenticationFailed)
+            },
+            _ => Err(ArchiveError::AuthenticationFailed)
+        };
+    }
+}
+fn check_hmac_key(key: &[u8]) -> Result<()> {
+    if key.len() < MIN_HMAC_KEY_LENGTH {
+        return Err(IOError::new(ErrorKind::InvalidInput, "HMAC keys must be at least 32 bytes").into());
+    }
+    return Ok(());
+}
+/// The key to make nonces from contents, derived from the encryption key
+fn nonce_key(encryption_key: &[u8; 32]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(encryption_key).unwrap();
+    mac.update(NONCE_KEY_LABEL);
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&mac.finalize().into_bytes());
+    return key;
+}
+fn invalid_key<E: ::std::error::Error + Send + Sync + 'static>(e: E) -> ArchiveError {
+    IOError::new(ErrorKind::InvalidInput, e).into()
+}
+
+impl ArchiveRead {
+    /// Opens the archive sealed by `SealingKeys`, verifying its signature(and decrypting) on memory.
+    /// Fails with `Unauthenticated` for archives not sealed, so modified asset packs cannot be loaded
+    pub fn open_sealed<P: AsRef<Path>>(path: P, keys: &ArchiveKeys, check_integrity: bool) -> Result<Self> {
+        let mut sealed = Vec::new();
+        File::open(path)?.read_to_end(&mut sealed)?;
+        return Self::from_bytes(keys.unseal(&sealed)?, check_integrity);
+    }
+}
@@ -0,0 +1,52 @@
+//! Overlaying Archives
+
+use super::*;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+/// Stacks archives in the order of priority(lowest first).
+/// An entry is served from the last archive that has it, and a tombstone in a later archive hides the entry of earlier ones,
+/// so patch archives(`peridot-archiver mkpatch`) can be stacked on a base archive.
+/// Entries are read through `&self`, so archives should be opened by `ArchiveRead::map_file`.
+pub struct ArchiveSet { archives: Vec<ArchiveRead> }
+impl ArchiveSet {
+    pub fn new() -> Self { ArchiveSet { archives: Vec::new() } }
+    /// Maps archives in the order of priority(lowest first).
+    pub fn open<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let mut this = Self::new();
+        for p in paths { this.push(ArchiveRead::map_file(p, false)?); }
+        return Ok(this);
+    }
+    /// Stacks an archive on top of the others(the highest priority).
+    pub fn push(&mut self, archive: ArchiveRead) -> &mut Self { self.archives.push(archive); return self; }
+    pub fn archives(&self) -> &[ArchiveRead] { &self.archives }
+    /// Applies `ArchiveRead::set_entry_verification` to all archives.
+    pub fn set_entry_verification(&mut self, enabled: bool) {
+        for a in &mut self.archives { a.set_entry_verification(enabled); }
+    }
+
+    /// The archive serving the entry. None if no archive has it or it is deleted by a tombstone
+    pub fn resolve(&self, path: &str) -> Option<&ArchiveRead> {
+        for a in self.archives.iter().rev() {
+            if a.contains(path) { return Some(a); }
+            if a.is_tombstone(path) { return None; }
+        }
+        return None;
+    }
+    pub fn contains(&self, path: &str) -> bool { self.resolve(path).is_some() }
+    pub fn read_slice(&self, path: &str) -> Result<Cow<'_, [u8]>> {
+        self.resolve(path).ok_or_else(|| ArchiveError::MissingEntry(path.to_owned()))?.read_slice(path)
+    }
+    pub fn read_shared(&self, path: &str) -> Result<EntryBytes> {
+        self.resolve(path).ok_or_else(|| ArchiveError::MissingEntry(path.to_owned()))?.read_shared(path)
+    }
+    /// Names of entries visible through the set, in order
+    pub fn entry_names(&self) -> BTreeSet<&str> {
+        let mut names = BTreeSet::new();
+        for a in &self.archives {
+            for n in a.tombstones() { names.remove(n); }
+            names.extend(a.entry_names());
+        }
+        return names;
+    }
+}
@@ -0,0 +1,108 @@
+//! Streaming Archive Writer
+
+use super::*;
+use std::io::{Seek, SeekFrom};
+
+/// Counts bytes and updates CRC-32 of everything written through(and the checksum of the entry being written)
+struct ChecksumWriter<W: Write> { inner: W, crc: u32, written: u64, entry: Option<EntryHasher> }
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        let w = self.inner.write(buf)?;
+        self.crc = crc32::update(self.crc, &crc32::IEEE_TABLE, &buf[..w]);
+        if let Some(ref mut h) = self.entry { h.update(&buf[..w]); }
+        self.written += w as u64;
+        return Ok(w);
+    }
+    fn flush(&mut self) -> IOResult<()> { self.inner.flush() }
+}
+
+/// Writes a random-access archive(`parr`) directly into the sink as entries are added.
+/// Only the entry table is kept on memory, and it is written after all contents at `finish`.
+/// As with `ArchiveWrite`, the output depends only on the sequence of added entries.
+/// Each entry has a CRC-32 of its stored bytes unless another method is set by `with_checksum`.
+pub struct ArchiveStreamWrite<W: Write + Seek> {
+    sink: ChecksumWriter<W>, comp: CompressionMethod, checksum: ChecksumMethod, level: Option<u32>,
+    dictionary: Option<Vec<u8>>, entries: BTreeMap<String, AssetEntryHeadingPair>, header_offset: u64
+}
+impl<W: Write + Seek> ArchiveStreamWrite<W> {
+    pub fn new(mut sink: W, comp: CompressionMethod) -> Result<Self> {
+        let header_offset = sink.seek(SeekFrom::Current(0))?;
+        // チェックサムとテーブル位置は`finish`で埋める
+        sink.write_all(b"parr")?; sink.write_all(&[0u8; 4 + 8])?;
+
+        return Ok(ArchiveStreamWrite {
+            sink: ChecksumWriter { inner: sink, crc: 0, written: 0, entry: None }, comp, checksum: ChecksumMethod::CRC32,
+            level: None, dictionary: None, entries: BTreeMap::new(), header_offset
+        });
+    }
+    /// Sets the algorithm of per-entry checksums(applied to entries added after this)
+    pub fn with_checksum(mut self, method: ChecksumMethod) -> Self { self.checksum = method; return self; }
+    /// Sets the compression level(applied to entries added after this)
+    pub fn with_compression_level(mut self, level: u32) -> Self { self.level = Some(level); return self; }
+    /// Compresses entries with the zstd dictionary and embeds it in the archive. Must be set before adding entries
+    pub fn with_dictionary(mut self, dictionary: Vec<u8>) -> Self { self.dictionary = Some(dictionary); return self; }
+
+    /// return -> false if the name has already been added
+    pub fn add(&mut self, name: String, content: &[u8]) -> Result<bool> { self.add_stream(name, content) }
+    /// Compresses the content from the reader into the sink without loading whole content on memory.
+    /// return -> false if the name has already been added
+    pub fn add_stream<R: Read>(&mut self, name: String, content: R) -> Result<bool> {
+        let comp = self.comp.clone();
+        self.add_stream_with(name, content, &comp, None)
+    }
+    /// `add_stream` with the compression method(and the level if specified) for this entry.
+    pub fn add_stream_with<R: Read>(&mut self, name: String, mut content: R, comp: &CompressionMethod, level: Option<u32>)
+            -> Result<bool> {
+        if self.entries.contains_key(&name) { return Ok(false); }
+        let relative_offset = self.sink.written;
+        self.sink.entry = Some(self.checksum.hasher());
+        let uncompressed_bytes = {
+            let mut e = comp.encoder(&mut self.sink, level.or(self.level), self.dictionary.as_ref().map(|d| &d[..]))?;
+            let ub = copy_chunked(&mut content, &mut e)?; e.finish()?; ub
+        };
+        let checksum = self.sink.entry.take().unwrap().finish();
+        self.entries.insert(name, AssetEntryHeadingPair {
+            relative_offset, byte_length: self.sink.written - relative_offset,
+            compression: comp.with_uncompressed_bytes(uncompressed_bytes), checksum, tombstone: false
+        });
+        return Ok(true);
+    }
+    /// Adds already compressed bytes as is(e.g. the result of `ArchiveRead::read_raw`).
+    /// return -> false if the name has already been added
+    pub fn add_raw(&mut self, name: String, stored: &[u8], comp: CompressionMethod) -> Result<bool> {
+        if self.entries.contains_key(&name) { return Ok(false); }
+        let relative_offset = self.sink.written;
+        self.sink.write_all(stored)?;
+        self.entries.insert(name, AssetEntryHeadingPair {
+            relative_offset, byte_length: stored.len() as _, compression: comp, checksum: self.checksum.checksum(stored),
+            tombstone: false
+        });
+        return Ok(true);
+    }
+    /// Adds a tombstone which deletes the entry of underlying archives in an `ArchiveSet`.
+    /// return -> false if the name has already been added
+    pub fn add_tombstone(&mut self, name: String) -> Result<bool> {
+        if self.entries.contains_key(&name) { return Ok(false); }
+        self.entries.insert(name, AssetEntryHeadingPair {
+            relative_offset: self.sink.written, byte_length: 0, compression: CompressionMethod::None,
+            checksum: EntryChecksum::None, tombstone: true
+        });
+        return Ok(true);
+    }
+
+    /// Writes the dictionary, the entry table and completes the header. return -> the sink
+    pub fn finish(mut self) -> Result<W> {
+        let dictionary_offset = self.sink.written;
+        if let Some(ref d) = self.dictionary { self.sink.write_all(d)?; }
+        let table_offset = self.sink.written;
+        write_asset_entries(&mut self.sink, &self.entries, true)?;
+        write_dictionary_heading(&mut self.sink, dictionary_offset, table_offset - dictionary_offset)?;
+        let ChecksumWriter { inner: mut sink, crc, .. } = self.sink;
+        let end = sink.seek(SeekFrom::Current(0))?;
+        sink.seek(SeekFrom::Start(self.header_offset + 4))?;
+        U32LE(crc).write(&mut sink)?;
+        U64LE(table_offset).write(&mut sink)?;
+        sink.seek(SeekFrom::Start(end))?;
+        return Ok(sink);
+    }
+}
@@ -0,0 +1,103 @@
+//! Borrowed Archive Reading
+
+use super::*;
+use std::borrow::Cow;
+use std::slice::Iter as SliceIter;
+
+/// An uncompressed(`par `) or random-access(`parr`) archive read directly from bytes on memory(e.g. `SharedMap::open`).
+/// Entry names and stored contents are borrowed from the bytes, so the entry table is parsed without allocating names
+pub struct ArchiveView<'d> {
+    /// 名前順に並べておき、二分探索で引く
+    entries: Vec<(&'d str, AssetEntryHeadingPair)>,
+    content: &'d [u8], dictionary: Option<&'d [u8]>, random_access: bool, verify_entries: bool
+}
+impl<'d> ArchiveView<'d> {
+    /// Fails with `CompressedBody` for whole-body compressed archives(`pard`/`parz`/`par1`)
+    pub fn new(archive: &'d [u8], check_integrity: bool) -> Result<Self> {
+        let mut reader = SliceReader::new(archive);
+        let (layout, crc) = read_file_header(&mut reader)?;
+        let body = reader.remaining();
+        if check_integrity {
+            let input_crc = crc32::checksum_ieee(body);
+            if input_crc != crc { return Err(ArchiveError::CRCMismatch { recorded: crc, input: input_crc }); }
+        }
+
+        let mut reader = SliceReader::new(body);
+        let (entries, content, dictionary, random_access) = match layout {
+            ArchiveLayout::RandomAccess(table_offset) => {
+                reader.seek(SeekFrom::Start(table_offset))?;
+                let entries = read_borrowed_entries(&mut reader, true)?;
+                let dictionary_heading = AssetEntryHeadingPair::read(&mut reader).map_err(truncated_as_table_error)?;
+                let dictionary = if dictionary_heading.byte_length == 0 { None } else {
+                    Some(content_slice(body, dictionary_heading.relative_offset, dictionary_heading.byte_length, "(dictionary)")?)
+                };
+                (entries, body, dictionary, true)
+            },
+            ArchiveLayout::WholeBody(CompressionMethod::None) => {
+                let entries = read_borrowed_entries(&mut reader, false)?;
+                (entries, reader.remaining(), None, false)
+            },
+            ArchiveLayout::WholeBody(_) => return Err(ArchiveError::CompressedBody)
+        };
+        return Ok(ArchiveView { entries, content, dictionary, random_access, verify_entries: false });
+    }
+
+    /// true if the archive has `parr` layout(entries are compressed individually)
+    pub fn is_random_access(&self) -> bool { self.random_access }
+    /// Where and how the entry is stored. Offsets are relative to the start of contents
+    pub fn entry_heading(&self, path: &str) -> Option<&AssetEntryHeadingPair> {
+        self.entries.binary_search_by(|&(n, _)| n.cmp(path)).ok().map(|i| &self.entries[i].1)
+    }
+    /// The zstd dictionary embedded in the archive
+    pub fn dictionary(&self) -> Option<&'d [u8]> { self.dictionary }
+    /// Validates per-entry checksums on every `stored_slice`/`read_slice`(disabled by default)
+    pub fn set_entry_verification(&mut self, enabled: bool) { self.verify_entries = enabled; }
+
+    /// true if the archive has the entry(and it is not deleted by a tombstone)
+    pub fn contains(&self, path: &str) -> bool { self.entry_heading(path).map_or(false, |e| !e.tombstone) }
+    /// true if the archive has a tombstone of the entry
+    pub fn is_tombstone(&self, path: &str) -> bool { self.entry_heading(path).map_or(false, |e| e.tombstone) }
+    /// Stored(possibly compressed) bytes of the entry
+    pub fn stored_slice(&self, path: &str) -> Result<&'d [u8]> {
+        let entry_pair = self.entry_heading(path).filter(|e| !e.tombstone)
+            .ok_or_else(|| ArchiveError::MissingEntry(path.to_owned()))?;
+        let slice = content_slice(self.content, entry_pair.relative_offset, entry_pair.byte_length, path)?;
+        if self.verify_entries { check_entry_checksum(path, &entry_pair.checksum, slice)?; }
+        return Ok(slice);
+    }
+    /// Content of the entry: borrowed for uncompressed entries, decompressed otherwise
+    pub fn read_slice(&self, path: &str) -> Result<Cow<'d, [u8]>> {
+        let stored = self.stored_slice(path)?;
+        return match self.entry_heading(path).unwrap().compression {
+            CompressionMethod::None => Ok(Cow::Borrowed(stored)),
+            ref c => c.decompress_slice(stored, self.dictionary).map(Cow::Owned).map_err(From::from)
+        };
+    }
+    /// Names of entries(except tombstones) in order
+    pub fn entry_names<'v>(&'v self) -> ArchiveViewEntryIterator<'v, 'd> { ArchiveViewEntryIterator(self.entries.iter(), false) }
+    /// Names of entries deleted by tombstones in order
+    pub fn tombstones<'v>(&'v self) -> ArchiveViewEntryIterator<'v, 'd> { ArchiveViewEntryIterator(self.entries.iter(), true) }
+}
+/// 第2要素がtrueなら削除済みエントリだけ、falseならそれ以外を列挙する
+pub struct ArchiveViewEntryIterator<'v, 'd: 'v>(SliceIter<'v, (&'d str, AssetEntryHeadingPair)>, bool);
+impl<'v, 'd> Iterator for ArchiveViewEntryIterator<'v, 'd> {
+    type Item = &'d str;
+    fn next(&mut self) -> Option<&'d str> {
+        let tombstones = self.1;
+        self.0.by_ref().find(|&&(_, ref e)| e.tombstone == tombstones).map(|&(n, _)| n)
+    }
+}
+
+fn read_borrowed_entries<'d>(reader: &mut SliceReader<'d>, random_access: bool) -> Result<Vec<(&'d str, AssetEntryHeadingPair)>> {
+    let VariableUInt64(count) = VariableUInt64::read(reader).map_err(truncated_as_table_error)?;
+    let mut elements = Vec::with_capacity(capacity_hint(count));
+    for _ in 0 .. count {
+        let heading = if random_access { AssetEntryHeadingPair::read_random_access(reader)? }
+            else { AssetEntryHeadingPair::read(reader).map_err(truncated_as_table_error)? };
+        let PascalStr(name) = PascalStr::read(reader).map_err(truncated_as_table_error)?;
+        elements.push((name, heading));
+    }
+    // 書き出し時には名前順になっているが、そうでない入力でも引けるように
+    if elements.windows(2).any(|w| w[0].0 > w[1].0) { elements.sort_by(|a, b| a.0.cmp(b.0)); }
+    return Ok(elements);
+}
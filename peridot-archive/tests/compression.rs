@@ -0,0 +1,38 @@
+//! Compression levels
+
+extern crate peridot_archive as par;
+
+/// Words picked pseudo-randomly, so matches appear at various distances
+fn sample_text() -> Vec<u8> {
+    let words: Vec<String> = (0 .. 1500u32).map(|i| format!("w{:x}", i.wrapping_mul(2654435761))).collect();
+    let mut state = 1u32;
+    let mut text = Vec::new();
+    for _ in 0 .. 20000 {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        text.extend_from_slice(words[(state >> 16) as usize % words.len()].as_bytes()); text.push(b' ');
+    }
+    return text;
+}
+
+#[test]
+fn higher_zlib_levels_compress_better() {
+    let text = sample_text();
+    let mut sizes = Vec::new();
+    for level in 0 ..= 9 {
+        let mut archive = par::ArchiveWrite::random_access(par::CompressionMethod::Zlib(0)).with_compression_level(level);
+        archive.add("a".to_owned(), text.clone()).unwrap();
+        let mut bytes = Vec::new();
+        archive.write(&mut bytes).unwrap();
+        let mut read = par::ArchiveRead::from_bytes(bytes.clone(), true).unwrap();
+        assert_eq!(read.read_bin("a").unwrap(), text, "level {}", level);
+        sizes.push(bytes.len());
+    }
+    for (level, pair) in sizes.windows(2).enumerate() {
+        assert!(pair[1] <= pair[0], "zlib:{}({} bytes) is larger than zlib:{}({} bytes)", level + 1, pair[1], level, pair[0]);
+    }
+    assert!(sizes[9] < sizes[1] && sizes[1] < sizes[0], "{:?}", sizes);
+    // 既定(None)は9と同じ
+    let (default, _) = par::CompressionMethod::Zlib(0).compress_entry(&text, None, None).unwrap();
+    let (best, _) = par::CompressionMethod::Zlib(0).compress_entry(&text, Some(9), None).unwrap();
+    assert_eq!(default, best);
+}
@@ -0,0 +1,33 @@
+//! Inputs in the fuzzing corpus(`fuzz/corpus/par_read`) must be rejected or read without panics
+
+extern crate peridot_archive as par;
+
+use std::fs::{read, read_dir};
+use std::path::Path;
+
+#[test]
+fn par_read_corpus() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("../fuzz/corpus/par_read");
+    for f in read_dir(corpus).unwrap() {
+        let path = f.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let opened = par::ArchiveRead::from_bytes(read(&path).unwrap(), false);
+        if name.starts_with("regression_") {
+            // 壊れた入力はどこかで必ずエラーになる
+            let failed = match opened {
+                Err(_) => true,
+                Ok(mut a) => {
+                    let names: Vec<String> = a.entry_names().map(ToOwned::to_owned).collect();
+                    names.iter().any(|n| a.read_bin(n).is_err())
+                }
+            };
+            assert!(failed, "{} was read without errors", name);
+        }
+        else {
+            let mut a = opened.unwrap_or_else(|e| panic!("{}: {}", name, e));
+            a.set_entry_verification(true);
+            let names: Vec<String> = a.entry_names().map(ToOwned::to_owned).collect();
+            for n in names { a.read_bin(&n).unwrap_or_else(|e| panic!("{}/{}: {}", name, n, e)); }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Exact byte layouts of archives(all fixed-width values are little-endian regardless of the host)
+
+extern crate peridot_archive as par;
+
+use std::fs::{remove_file, write};
+use std::io::Cursor;
+
+/// `a` = "xy" in a random-access archive without compression and checksums
+const RANDOM_ACCESS: &[u8] = &[
+    b'p', b'a', b'r', b'r', 0xf5, 0x91, 0x4b, 0x1a, 2, 0, 0, 0, 0, 0, 0, 0,
+    // contents
+    b'x', b'y',
+    // entry table: count, byte_length, relative_offset, compression, uncompressed bytes, checksum method, checksum, name
+    1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, b'a',
+    // dictionary heading: byte_length, relative_offset
+    0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0
+];
+/// `a` = "xy" in an uncompressed solid archive
+const SOLID: &[u8] = &[
+    b'p', b'a', b'r', b' ', 0x8d, 0xcc, 0x86, 0x2b,
+    // entry table: count, byte_length, relative_offset, name
+    1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, b'a',
+    // contents
+    b'x', b'y'
+];
+
+fn write_archive(mut archive: par::ArchiveWrite) -> Vec<u8> {
+    archive.add("a".to_owned(), b"xy".to_vec()).unwrap();
+    let mut bytes = Vec::new(); archive.write(&mut bytes).unwrap();
+    return bytes;
+}
+fn read_archive(name: &str, bytes: &[u8]) -> Vec<u8> {
+    let path = std::env::temp_dir().join(format!("peridot-archive-layout-{}-{}.par", name, std::process::id()));
+    write(&path, bytes).unwrap();
+    let content = par::ArchiveRead::from_file(&path, true).and_then(|mut a| a.read_bin("a"));
+    remove_file(&path).unwrap();
+    return content.unwrap();
+}
+
+#[test]
+fn random_access_layout() {
+    let archive = par::ArchiveWrite::random_access(par::CompressionMethod::None).with_checksum(par::ChecksumMethod::None);
+    assert_eq!(write_archive(archive), RANDOM_ACCESS);
+}
+#[test]
+fn stream_writes_random_access_layout() {
+    let mut archive = par::ArchiveStreamWrite::new(Cursor::new(Vec::new()), par::CompressionMethod::None).unwrap()
+        .with_checksum(par::ChecksumMethod::None);
+    archive.add("a".to_owned(), b"xy").unwrap();
+    assert_eq!(archive.finish().unwrap().into_inner(), RANDOM_ACCESS);
+}
+#[test]
+fn solid_layout() {
+    assert_eq!(write_archive(par::ArchiveWrite::new(par::CompressionMethod::None)), SOLID);
+}
+#[test]
+fn compressed_solid_header() {
+    let bytes = write_archive(par::ArchiveWrite::new(par::CompressionMethod::Lz4(0)));
+    assert_eq!(&bytes[..4], b"parz");
+    // 展開後のサイズ(エントリテーブル + 内容)
+    assert_eq!(&bytes[4..12], &[(SOLID.len() - 8) as u8, 0, 0, 0, 0, 0, 0, 0]);
+}
+#[test]
+fn read_layouts() {
+    assert_eq!(read_archive("random-access", RANDOM_ACCESS), b"xy");
+    assert_eq!(read_archive("solid", SOLID), b"xy");
+}
+#[test]
+fn view_layouts() {
+    let view = par::ArchiveView::new(RANDOM_ACCESS, true).unwrap();
+    assert!(view.is_random_access());
+    assert_eq!(view.entry_names().collect::<Vec<_>>(), ["a"]);
+    let content = view.read_slice("a").unwrap();
+    assert_eq!(&content[..], b"xy");
+    // 無圧縮のエントリは入力から借用される
+    assert_eq!(content.as_ptr(), RANDOM_ACCESS[16..].as_ptr());
+
+    let view = par::ArchiveView::new(SOLID, true).unwrap();
+    assert_eq!(view.stored_slice("a").unwrap(), b"xy");
+    assert!(view.stored_slice("b").is_err());
+}
+#[test]
+fn entry_heading_values() {
+    let path = std::env::temp_dir().join(format!("peridot-archive-layout-heading-{}.par", std::process::id()));
+    write(&path, RANDOM_ACCESS).unwrap();
+    let archive = par::ArchiveRead::from_file(&path, true);
+    remove_file(&path).unwrap();
+    let archive = archive.unwrap();
+    assert_eq!(archive.recorded_crc32(), 0x1a4b91f5);
+    let heading = archive.entry_heading("a").unwrap();
+    assert_eq!((heading.byte_length, heading.relative_offset), (2, 0));
+}
@@ -0,0 +1,43 @@
+//! Mapping between entry names and file paths
+
+extern crate peridot_archive as par;
+
+use std::path::Path;
+
+#[test]
+fn entry_output_path_stays_under_base() {
+    let base = Path::new("out");
+    assert_eq!(par::entry_output_path(base, "shaders/pass.pvp"), Some(base.join("shaders").join("pass.pvp")));
+    assert_eq!(par::entry_output_path(base, "./a//b.png"), Some(base.join("a").join("b.png")));
+    assert_eq!(par::entry_output_path(base, "a\\b.png"), Some(base.join("a").join("b.png")));
+}
+#[test]
+fn entry_output_path_rejects_escaping_names() {
+    let base = Path::new("out");
+    for name in &["../a", "a/../../b", "a/..", "..\\a", "/etc/passwd", "\\a", "C:/a", "C:\\a", "a/C:b", "C:a"] {
+        assert_eq!(par::entry_output_path(base, name), None, "{:?} must be rejected", name);
+    }
+}
+
+#[test]
+fn portable_entry_name_with_current_directory_base() {
+    for base in &[".", "./", ""] {
+        assert_eq!(par::portable_entry_name("a/b.png", base).as_ref().map(|s| &s[..]), Some("a/b.png"), "base {:?}", base);
+        assert_eq!(par::portable_entry_name("./a/b.png", base).as_ref().map(|s| &s[..]), Some("a/b.png"), "base {:?}", base);
+    }
+    assert_eq!(par::portable_entry_name(".", "."), None);
+    assert_eq!(par::portable_entry_name("../a.png", "."), None);
+}
+#[test]
+fn portable_entry_name_with_trailing_separator() {
+    assert_eq!(par::portable_entry_name("assets/a/b.png", "assets/").as_ref().map(|s| &s[..]), Some("a/b.png"));
+    assert_eq!(par::portable_entry_name("assets/a/", "assets").as_ref().map(|s| &s[..]), Some("a"));
+    assert_eq!(par::portable_entry_name("assets/", "assets/"), None);
+}
+#[test]
+fn portable_entry_name_with_backslashes() {
+    assert_eq!(par::portable_entry_name("assets\\a\\b.png", "assets").as_ref().map(|s| &s[..]), Some("a/b.png"));
+    assert_eq!(par::portable_entry_name("assets/a\\b.png", "assets\\").as_ref().map(|s| &s[..]), Some("a/b.png"));
+    assert_eq!(par::portable_entry_name(".\\a.png", ".").as_ref().map(|s| &s[..]), Some("a.png"));
+    assert_eq!(par::portable_entry_name("assets\\..\\a.png", "assets"), None);
+}
@@ -0,0 +1,102 @@
+//! Sealing archives and rejecting modified or unsealed ones(`security` feature)
+#![cfg(feature = "security")]
+
+extern crate peridot_archive as par;
+
+use std::fs::{remove_file, write};
+
+fn archive_bytes() -> Vec<u8> {
+    let mut archive = par::ArchiveWrite::random_access(par::CompressionMethod::None);
+    archive.add("a".to_owned(), b"sealed content".to_vec()).unwrap();
+    let mut bytes = Vec::new();
+    archive.write(&mut bytes).unwrap();
+    return bytes;
+}
+fn seal(keys: &par::SealingKeys) -> Vec<u8> {
+    let mut sealed = Vec::new();
+    keys.seal(&archive_bytes(), &mut sealed).unwrap();
+    return sealed;
+}
+fn open(name: &str, bytes: &[u8], keys: &par::ArchiveKeys) -> par::Result<par::ArchiveRead> {
+    let path = std::env::temp_dir().join(format!("peridot-archive-sealed-{}-{}.par", name, std::process::id()));
+    write(&path, bytes).unwrap();
+    let archive = par::ArchiveRead::open_sealed(&path, keys, true);
+    remove_file(&path).unwrap();
+    return archive;
+}
+fn assert_content(archive: par::Result<par::ArchiveRead>) {
+    assert_eq!(archive.unwrap().read_bin("a").unwrap(), b"sealed content");
+}
+fn assert_rejected(archive: par::Result<par::ArchiveRead>) {
+    match archive {
+        Err(par::ArchiveError::AuthenticationFailed) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("the archive must be rejected")
+    }
+}
+
+const SECRET: [u8; 32] = [7; 32];
+const HMAC_KEY: [u8; 32] = [9; 32];
+const ENCRYPTION_KEY: [u8; 32] = [3; 32];
+
+#[test]
+fn ed25519_round_trip() {
+    let sealing = par::SealingKeys::ed25519(&SECRET).unwrap();
+    let keys = par::ArchiveKeys::ed25519(&sealing.public_key().unwrap()).unwrap();
+    assert_content(open("ed25519", &seal(&sealing), &keys));
+
+    let sealing = sealing.with_encryption_key(ENCRYPTION_KEY);
+    let sealed = seal(&sealing);
+    assert!(sealed.windows(14).all(|w| w != b"sealed content"));
+    match open("ed25519-nokey", &sealed, &keys) {
+        Err(par::ArchiveError::KeyRequired) => (), r => panic!("unexpected {:?}", r.err())
+    }
+    assert_content(open("ed25519-encrypted", &sealed, &keys.with_decryption_key(ENCRYPTION_KEY)));
+}
+#[test]
+fn hmac_round_trip() {
+    let sealing = par::SealingKeys::hmac(HMAC_KEY.to_vec()).unwrap().with_encryption_key(ENCRYPTION_KEY);
+    let keys = par::ArchiveKeys::hmac(HMAC_KEY.to_vec()).unwrap().with_decryption_key(ENCRYPTION_KEY);
+    assert_content(open("hmac", &seal(&sealing), &keys));
+}
+#[test]
+fn flipped_ciphertext_byte_is_rejected() {
+    let sealing = par::SealingKeys::hmac(HMAC_KEY.to_vec()).unwrap().with_encryption_key(ENCRYPTION_KEY);
+    let keys = par::ArchiveKeys::hmac(HMAC_KEY.to_vec()).unwrap().with_decryption_key(ENCRYPTION_KEY);
+    let mut sealed = seal(&sealing);
+    // ヘッダ(28バイト)の直後から暗号文
+    sealed[40] ^= 0x01;
+    assert_rejected(open("hmac-flipped", &sealed, &keys));
+
+    let sealing = par::SealingKeys::ed25519(&SECRET).unwrap();
+    let keys = par::ArchiveKeys::ed25519(&sealing.public_key().unwrap()).unwrap();
+    let mut sealed = seal(&sealing);
+    sealed[40] ^= 0x01;
+    assert_rejected(open("ed25519-flipped", &sealed, &keys));
+}
+#[test]
+fn wrong_key_is_rejected() {
+    let sealed = seal(&par::SealingKeys::hmac(HMAC_KEY.to_vec()).unwrap());
+    assert_rejected(open("hmac-wrong", &sealed, &par::ArchiveKeys::hmac(vec![10; 32]).unwrap()));
+
+    let sealed = seal(&par::SealingKeys::ed25519(&SECRET).unwrap());
+    let other = par::SealingKeys::ed25519(&[8; 32]).unwrap().public_key().unwrap();
+    assert_rejected(open("ed25519-wrong", &sealed, &par::ArchiveKeys::ed25519(&other).unwrap()));
+    // 認証方式が違う鍵
+    assert_rejected(open("ed25519-hmac", &sealed, &par::ArchiveKeys::hmac(HMAC_KEY.to_vec()).unwrap()));
+}
+#[test]
+fn unsealed_archive_is_rejected() {
+    match open("unsealed", &archive_bytes(), &par::ArchiveKeys::hmac(HMAC_KEY.to_vec()).unwrap()) {
+        Err(par::ArchiveError::Unauthenticated) => (), r => panic!("unexpected {:?}", r.err())
+    }
+}
+#[test]
+fn short_hmac_key_is_rejected() {
+    assert!(par::SealingKeys::hmac(Vec::new()).is_err());
+    assert!(par::SealingKeys::hmac(vec![1; 31]).is_err());
+    assert!(par::SealingKeys::hmac(vec![1; 32]).is_ok());
+    assert!(par::ArchiveKeys::hmac(Vec::new()).is_err());
+    assert!(par::ArchiveKeys::hmac(vec![1; 31]).is_err());
+    assert!(par::ArchiveKeys::hmac(vec![1; 32]).is_ok());
+}
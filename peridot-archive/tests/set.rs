@@ -0,0 +1,51 @@
+//! Resolving entries through stacked archives
+
+extern crate peridot_archive as par;
+
+use std::io::Cursor;
+
+fn archive(entries: &[(&str, &[u8])], tombstones: &[&str]) -> par::ArchiveRead {
+    let mut archive = par::ArchiveStreamWrite::new(Cursor::new(Vec::new()), par::CompressionMethod::None).unwrap();
+    for &(name, content) in entries { archive.add(name.to_owned(), content).unwrap(); }
+    for &name in tombstones { archive.add_tombstone(name.to_owned()).unwrap(); }
+    return par::ArchiveRead::from_bytes(archive.finish().unwrap().into_inner(), true).unwrap();
+}
+fn content(set: &par::ArchiveSet, name: &str) -> Vec<u8> { set.read_slice(name).unwrap().into_owned() }
+
+#[test]
+fn later_archive_wins() {
+    let mut set = par::ArchiveSet::new();
+    set.push(archive(&[("a", b"base a"), ("b", b"base b")], &[]));
+    set.push(archive(&[("b", b"patch b"), ("c", b"patch c")], &[]));
+    assert_eq!(content(&set, "a"), b"base a");
+    assert_eq!(content(&set, "b"), b"patch b");
+    assert_eq!(content(&set, "c"), b"patch c");
+    assert_eq!(set.entry_names().into_iter().collect::<Vec<_>>(), ["a", "b", "c"]);
+    // 順番を入れ替えると逆になる
+    let mut reversed = par::ArchiveSet::new();
+    reversed.push(archive(&[("b", b"patch b")], &[]));
+    reversed.push(archive(&[("b", b"base b")], &[]));
+    assert_eq!(content(&reversed, "b"), b"base b");
+}
+#[test]
+fn tombstone_hides_lower_entries() {
+    let mut set = par::ArchiveSet::new();
+    set.push(archive(&[("a", b"base a"), ("b", b"base b")], &[]));
+    set.push(archive(&[("c", b"patch c")], &["a"]));
+    assert!(set.resolve("a").is_none());
+    assert!(!set.contains("a"));
+    match set.read_slice("a") {
+        Err(par::ArchiveError::MissingEntry(ref n)) if n == "a" => (),
+        r => panic!("unexpected {:?}", r.map(|b| b.into_owned()))
+    }
+    assert_eq!(set.entry_names().into_iter().collect::<Vec<_>>(), ["b", "c"]);
+}
+#[test]
+fn entry_above_tombstone_is_visible() {
+    let mut set = par::ArchiveSet::new();
+    set.push(archive(&[("a", b"base a")], &[]));
+    set.push(archive(&[], &["a"]));
+    set.push(archive(&[("a", b"restored a")], &[]));
+    assert_eq!(content(&set, "a"), b"restored a");
+    assert_eq!(set.entry_names().into_iter().collect::<Vec<_>>(), ["a"]);
+}
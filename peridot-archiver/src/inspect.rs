@@ -0,0 +1,75 @@
+//! info/diff: inspecting archives
+
+use par;
+use clap::ArgMatches;
+use std::collections::BTreeSet;
+use std::io::{stdout, StdoutLock, Write, ErrorKind};
+use keys::open_archive;
+
+pub fn info(args: &ArgMatches) -> par::Result<()> {
+    let archive = open_archive(args, "arc", false)?;
+    return to_stdout(|out| write_info(&archive, out));
+}
+fn write_info<W: Write>(archive: &par::ArchiveRead, out: &mut W) -> par::Result<()> {
+    writeln!(out, "Signature: {:?}{}", String::from_utf8_lossy(archive.signature()),
+        if archive.is_random_access() { " (random access)" } else { "" })?;
+    if !archive.is_random_access() {
+        writeln!(out, "Compression: {}", method_name(archive.compression_method()))?;
+    }
+    let uncompressed_bytes: u64 = archive.entry_names().map(|n| entry_uncompressed_bytes(archive.entry_heading(n).unwrap())).sum();
+    writeln!(out, "Uncompressed Size: {} bytes", archive.compression_method().uncompressed_bytes().unwrap_or(uncompressed_bytes))?;
+    writeln!(out, "CRC-32: 0x{:08x}", archive.recorded_crc32())?;
+    if let Some(d) = archive.dictionary() { writeln!(out, "Dictionary: {} bytes", d.len())?; }
+    writeln!(out, "Entries: {}", archive.entry_names().count())?;
+    let tombstones = archive.tombstones().count();
+    if tombstones > 0 { writeln!(out, "Tombstones: {}", tombstones)?; }
+
+    writeln!(out, "{:>12} {:>12} {:>12} {:<8} {:<26} NAME", "OFFSET", "LENGTH", "UNCOMPRESSED", "METHOD", "CHECKSUM")?;
+    for n in archive.entry_names() {
+        let h = archive.entry_heading(n).unwrap();
+        writeln!(out, "{:>12} {:>12} {:>12} {:<8} {:<26} {}", h.relative_offset, h.byte_length, entry_uncompressed_bytes(h),
+            method_name(&h.compression), h.checksum.to_string(), n)?;
+    }
+    for n in archive.tombstones() { writeln!(out, "{:>12} {:>12} {:>12} {:<8} {:<26} {}", "-", "-", "-", "deleted", "-", n)?; }
+    return Ok(());
+}
+/// Lists added(A), removed(D) and modified(M) entries from the first archive to the second one.
+/// Entries are compared by hashes of their uncompressed contents, so recompressed entries are not reported
+pub fn diff(args: &ArgMatches) -> par::Result<()> {
+    let (a, b) = (open_archive(args, "arc", false)?, open_archive(args, "arc2", false)?);
+
+    let names: BTreeSet<&str> = a.entry_names().chain(b.entry_names()).collect();
+    return to_stdout(|out| {
+        for n in names {
+            match (a.contains(n), b.contains(n)) {
+                (false, true) => writeln!(out, "A {}", n)?,
+                (true, false) => writeln!(out, "D {}", n)?,
+                _ => if content_hash(&a, n)? != content_hash(&b, n)? { writeln!(out, "M {}", n)?; }
+            }
+        }
+        return Ok(());
+    });
+}
+
+/// Writes to the locked stdout. Closed pipes(e.g. `| head`) end the output quietly
+fn to_stdout<F: FnOnce(&mut StdoutLock) -> par::Result<()>>(f: F) -> par::Result<()> {
+    let stdout = stdout();
+    let mut out = stdout.lock();
+    return match f(&mut out).and_then(|_| out.flush().map_err(From::from)) {
+        Err(par::ArchiveError::IO(ref e)) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        r => r
+    };
+}
+
+fn content_hash(archive: &par::ArchiveRead, path: &str) -> par::Result<par::EntryChecksum> {
+    archive.read_slice(path).map(|b| par::ChecksumMethod::XXH64.checksum(&b))
+}
+fn entry_uncompressed_bytes(h: &par::AssetEntryHeadingPair) -> u64 {
+    h.compression.uncompressed_bytes().unwrap_or(h.byte_length)
+}
+fn method_name(m: &par::CompressionMethod) -> &'static str {
+    match *m {
+        par::CompressionMethod::None => "none", par::CompressionMethod::Zlib(_) => "zlib",
+        par::CompressionMethod::Lz4(_) => "lz4", par::CompressionMethod::Zstd(_) => "zstd"
+    }
+}
@@ -0,0 +1,94 @@
+//! keygen and options for sealed(signed/encrypted) archives
+
+use par;
+use getrandom::getrandom;
+use clap::{Arg, ArgMatches};
+use std::fs::{read, File, OpenOptions};
+use std::io::{Result as IOResult, Error as IOError, ErrorKind};
+use std::io::prelude::Write;
+
+/// Writes a random Ed25519 secret key to FILE and its public key to FILE.pub,
+/// or a random 256bit key for `--hmac-key`/`--encrypt-key` with `--symmetric`
+pub fn keygen(args: &ArgMatches) -> par::Result<()> {
+    let path = args.value_of("ofile").unwrap();
+    let mut key = [0u8; 32];
+    getrandom(&mut key).map_err(|e| IOError::new(ErrorKind::Other, e.to_string()))?;
+    if !args.is_present("symmetric") {
+        let public_key = par::SealingKeys::ed25519(&key)?.public_key().unwrap();
+        File::create(format!("{}.pub", path))?.write_all(&public_key)?;
+    }
+    create_secret_file(path)?.write_all(&key)?;
+    return Ok(());
+}
+/// Creates(or truncates) the file readable only by the owner on Unix
+fn create_secret_file(path: &str) -> IOResult<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)] { use std::os::unix::fs::OpenOptionsExt; options.mode(0o600); }
+    let file = options.open(path)?;
+    // 既存のファイルにはmodeが効かないので改めて設定する
+    #[cfg(unix)] { use std::os::unix::fs::PermissionsExt; file.set_permissions(PermissionsExt::from_mode(0o600))?; }
+    return Ok(file);
+}
+
+pub fn sealing_args() -> [Arg<'static, 'static>; 3] {
+    [
+        Arg::with_name("sign_key").long("sign-key").value_name("FILE").takes_value(true).conflicts_with("hmac_key")
+            .help("Signs the archive with the Ed25519 secret key(made by keygen)"),
+        Arg::with_name("hmac_key").long("hmac-key").value_name("FILE").takes_value(true)
+            .help("Authenticates the archive with HMAC-SHA256 using the key"),
+        Arg::with_name("encrypt_key").long("encrypt-key").value_name("FILE").takes_value(true)
+            .help("Encrypts the archive with ChaCha20-Poly1305 using the 32-byte key(requires --sign-key or --hmac-key)")
+    ]
+}
+pub fn key_args() -> [Arg<'static, 'static>; 3] {
+    [
+        Arg::with_name("verify_key").long("verify-key").value_name("FILE").takes_value(true).conflicts_with("hmac_key")
+            .help("Opens the sealed archive, verifying its signature with the Ed25519 public key"),
+        Arg::with_name("hmac_key").long("hmac-key").value_name("FILE").takes_value(true)
+            .help("Opens the sealed archive, verifying its HMAC-SHA256 with the key"),
+        Arg::with_name("decrypt_key").long("decrypt-key").value_name("FILE").takes_value(true)
+            .help("Decrypts the sealed archive with the 32-byte key")
+    ]
+}
+
+/// `key_args` with `--sign-key`/`--encrypt-key`, for rewriting sealed archives(`--hmac-key` is used for both)
+pub fn resealing_args() -> [Arg<'static, 'static>; 5] {
+    let [verify_key, _, decrypt_key] = key_args();
+    let [sign_key, _, encrypt_key] = sealing_args();
+    [
+        verify_key, decrypt_key, sign_key, encrypt_key,
+        Arg::with_name("hmac_key").long("hmac-key").value_name("FILE").takes_value(true)
+            .help("Opens the sealed archive and seals it again with HMAC-SHA256 using the key")
+    ]
+}
+
+/// None if the archive is not to be sealed
+pub fn sealing_keys(args: &ArgMatches) -> par::Result<Option<par::SealingKeys>> {
+    let keys = match (args.value_of("sign_key"), args.value_of("hmac_key")) {
+        (Some(p), _) => par::SealingKeys::ed25519(&read(p)?)?,
+        (None, Some(p)) => par::SealingKeys::hmac(read(p)?)?,
+        _ if args.is_present("encrypt_key") => return Err(IOError::new(ErrorKind::InvalidInput,
+            "Encrypted archives must be signed(specify --sign-key or --hmac-key)").into()),
+        _ => return Ok(None)
+    };
+    return match args.value_of("encrypt_key") {
+        Some(p) => Ok(Some(keys.with_encryption_key(read_key32(p)?))), None => Ok(Some(keys))
+    };
+}
+/// Opens the archive specified as the argument `name`. Sealed archives are opened with the keys(`key_args`)
+pub fn open_archive(args: &ArgMatches, name: &str, check_integrity: bool) -> par::Result<par::ArchiveRead> {
+    let path = args.value_of(name).unwrap();
+    let keys = match (args.value_of("verify_key"), args.value_of("hmac_key")) {
+        (Some(p), _) => par::ArchiveKeys::ed25519(&read(p)?)?,
+        (None, Some(p)) => par::ArchiveKeys::hmac(read(p)?)?,
+        _ => return par::ArchiveRead::map_file(path, check_integrity)
+    };
+    let keys = match args.value_of("decrypt_key") { Some(p) => keys.with_decryption_key(read_key32(p)?), None => keys };
+    return par::ArchiveRead::open_sealed(path, &keys, check_integrity);
+}
+fn read_key32(path: &str) -> par::Result<[u8; 32]> {
+    let bytes = read(path)?;
+    if bytes.len() != 32 { return Err(IOError::new(ErrorKind::InvalidData, format!("{} is not a 32-byte key", path)).into()); }
+    let mut key = [0u8; 32]; key.copy_from_slice(&bytes); return Ok(key);
+}
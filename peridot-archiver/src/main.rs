@@ -1,80 +1,461 @@
 
-extern crate peridot_serialization_utils;
-extern crate clap; extern crate glob; mod par; extern crate libc;
-extern crate crc; extern crate lz4; extern crate libflate; extern crate zstd;
-use clap::{App, Arg, ArgMatches};
-use std::fs::{metadata, read_dir, read, File};
-use std::io::prelude::Write;
-use std::io::Result as IOResult;
+extern crate peridot_archive as par;
+extern crate clap; extern crate glob; extern crate libc;
+extern crate toml; extern crate serde; #[macro_use] extern crate serde_derive; extern crate getrandom;
+mod update; mod inspect; mod manifest; mod keys;
+use clap::{App, Arg, ArgMatches, Values};
+use std::fs::{metadata, read_dir, read, create_dir_all, remove_file, File};
+use std::io::prelude::{Read, Write, Seek, BufRead};
+use std::io::{BufWriter, BufReader, Cursor};
+use std::io::{Result as IOResult, Error as IOError, ErrorKind};
+use std::thread;
 
-fn extract(args: &ArgMatches) {
-    let mut archive = par::ArchiveRead::from_file(args.value_of("arc").unwrap(), args.is_present("check")).unwrap();
+fn extract(args: &ArgMatches) -> par::Result<()> {
+    let mut archive = keys::open_archive(args, "arc", args.is_present("check"))?;
+    archive.set_entry_verification(args.is_present("check"));
 
-    if let Some(apath) = args.value_of("apath") {
-        if let Some(b) = archive.read_bin(apath).unwrap() {
-            let foptr = unsafe { libc::fdopen(libc::dup(1), "wb\x00".as_ptr() as *const _) };
-            NativeOfstream::from_stream_ptr(foptr).unwrap().write_all(&b[..]).unwrap();
-        }
-        else {
-            panic!("Entry not found in archive: {}", apath);
+    if let Some(dir) = args.value_of("dir") {
+        return extract_to_directory(&archive, Path::new(dir), args.values_of("apath"));
+    }
+    if let Some(mut apaths) = args.values_of("apath") {
+        if apaths.len() > 1 {
+            return Err(IOError::new(ErrorKind::InvalidInput,
+                "Only one ASSET_PATH can be written to stdout(specify -C to extract several entries)").into());
         }
+        let b = archive.read_slice(apaths.next().unwrap())?;
+        let foptr = unsafe { libc::fdopen(libc::dup(1), "wb\x00".as_ptr() as *const _) };
+        NativeOfstream::from_stream_ptr(foptr).ok_or_else(IOError::last_os_error)?.write_all(&b[..])?;
+        return Ok(());
+    }
+    return Err(IOError::new(ErrorKind::InvalidInput, "Either ASSET_PATH or an output directory(-C) must be specified").into());
+}
+/// Extracts entries matching any of patterns(or all entries if no patterns are specified) under the directory.
+fn extract_to_directory(archive: &par::ArchiveRead, dir: &Path, patterns: Option<Values>) -> par::Result<()> {
+    let patterns = patterns.map_or(Ok(Vec::new()), |ps| ps.map(glob::Pattern::new).collect::<Result<Vec<_>, _>>())
+        .map_err(|e| IOError::new(ErrorKind::InvalidInput, e.msg))?;
+    let mut targets = Vec::new();
+    for n in archive.entry_names().filter(|n| patterns.is_empty() || patterns.iter().any(|p| p.matches(n))) {
+        let path = par::entry_output_path(dir, n).ok_or_else(|| IOError::new(ErrorKind::InvalidData,
+            format!("Refusing to extract {:?} outside the output directory", n)))?;
+        targets.push((n.to_owned(), path));
+    }
+    targets.sort();
+
+    for (n, path) in targets {
+        if let Some(parent) = path.parent() { create_dir_all(parent)?; }
+        File::create(&path)?.write_all(&archive.read_slice(&n)?)?;
     }
+    return Ok(());
 }
-fn list(args: &ArgMatches) {
-    let archive = par::ArchiveRead::from_file(args.value_of("arc").unwrap(), args.is_present("check")).unwrap();
+fn list(args: &ArgMatches) -> par::Result<()> {
+    let archive = keys::open_archive(args, "arc", args.is_present("check"))?;
 
     for n in archive.entry_names() {
         println!("{}", n);
     }
+    return Ok(());
+}
+/// Reports every corrupted entry. Entries without checksums(e.g. in whole-body compressed archives)
+/// are covered only by the checksum of the whole body.
+fn verify(args: &ArgMatches) -> par::Result<()> {
+    let body_intact = match keys::open_archive(args, "arc", true) {
+        Ok(_) => true,
+        Err(e @ par::ArchiveError::CRCMismatch { .. }) => { println!("{}", e); false },
+        Err(e) => return Err(e)
+    };
+
+    let mut archive = keys::open_archive(args, "arc", false)?;
+    let names: Vec<String> = archive.entry_names().map(ToOwned::to_owned).collect();
+    let (mut corrupted, mut unchecked) = (0, 0);
+    for n in &names {
+        match archive.verify_entry(n) {
+            Ok(true) => if args.is_present("verbose") { println!("OK {}", n); },
+            Ok(false) => unchecked += 1,
+            Err(e @ par::ArchiveError::EntryChecksumMismatch { .. }) => { corrupted += 1; println!("{}", e); },
+            Err(e) => { corrupted += 1; println!("Corrupted Entry {}: {}", n, e); }
+        }
+    }
+    println!("{} entries: {} corrupted, {} without checksum", names.len(), corrupted, unchecked);
+    if !body_intact || corrupted > 0 { return Err(IOError::new(ErrorKind::InvalidData, "The archive is corrupted").into()); }
+    return Ok(());
 }
 fn main() {
     let extract_matcher = App::new("extract").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
         .arg(Arg::with_name("arc").value_name("FILE").required(true).help("Archive file"))
-        .arg(Arg::with_name("apath").value_name("ASSET_PATH").help("An Asset Path (Optional)"))
-        .arg(Arg::with_name("check").long("check-integrity").help("Checks an archive integrity by checksum"));
+        .arg(Arg::with_name("apath").value_name("ASSET_PATH").multiple(true)
+            .help("An Asset Path to be written to stdout, or glob patterns of entries to be extracted with -C(multiple)"))
+        .arg(Arg::with_name("dir").short("C").long("directory").value_name("DIR").takes_value(true)
+            .help("Extracts all(or matching) entries as files under the directory"))
+        .arg(Arg::with_name("check").long("check-integrity").help("Checks an archive integrity by checksum"))
+        .args(&keys::key_args());
     let ls_matcher = App::new("list").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
         .arg(Arg::with_name("arc").value_name("FILE").required(true).help("Archive file"))
-        .arg(Arg::with_name("check").long("check-integrity").help("Checks an archive integrity by checksum"));
+        .arg(Arg::with_name("check").long("check-integrity").help("Checks an archive integrity by checksum"))
+        .args(&keys::key_args());
+    let verify_matcher = App::new("verify").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
+        .about("Checks checksums of the archive and each entry, and reports corrupted entries")
+        .arg(Arg::with_name("arc").value_name("FILE").required(true).help("Archive file"))
+        .arg(Arg::with_name("verbose").short("v").long("verbose").help("Prints intact entries too"))
+        .args(&keys::key_args());
+    let info_matcher = App::new("info").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
+        .about("Shows the header and the entry table of the archive")
+        .arg(Arg::with_name("arc").value_name("FILE").required(true).help("Archive file"))
+        .args(&keys::key_args());
+    let diff_matcher = App::new("diff").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
+        .about("Lists added(A), removed(D) and modified(M) entries between two archives")
+        .arg(Arg::with_name("arc").value_name("OLD").required(true).help("Archive file to compare from"))
+        .arg(Arg::with_name("arc2").value_name("NEW").required(true).help("Archive file to compare to"))
+        .args(&keys::key_args());
+    let mkpatch_matcher = App::new("mkpatch").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
+        .about("Makes a patch archive which turns BASE into NEW when stacked on it(added/modified entries and tombstones of removed ones). \
+            Sealed archives are opened with the keys, and the patch is sealed with --sign-key/--hmac-key/--encrypt-key")
+        .arg(Arg::with_name("arc").value_name("BASE").required(true).help("Archive file to be patched"))
+        .arg(Arg::with_name("arc2").value_name("NEW").required(true).help("Archive file to be reproduced"))
+        .arg(Arg::with_name("ofile").short("o").long("output").value_name("FILE").required(true).help("Describes where patch archive will be written"))
+        .arg(compression_method_arg())
+        .arg(checksum_method_arg())
+        .args(&keys::resealing_args());
     let create_matcher = App::new("new").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
         .arg(Arg::with_name("ofile").short("o").long("output").value_name("FILE").help("Describes where archive file will be written"))
+        .arg(Arg::with_name("ifiled").help("Input File/Directory").required_unless("manifest").multiple(true))
+        .arg(Arg::with_name("manifest").long("manifest").value_name("FILE").takes_value(true).conflicts_with_all(&["ifiled", "base_dir"])
+            .help("Packs files listed in the manifest(TOML) instead of input files/directories"))
+        .args(&naming_args())
+        .arg(compression_method_arg())
+        .arg(checksum_method_arg().conflicts_with("solid"))
+        .arg(Arg::with_name("train_dict").long("train-dict").conflicts_with("solid")
+            .help("Trains a zstd dictionary from the input files and embeds it in the archive(all entries must be compressed with zstd)"))
+        .arg(Arg::with_name("dict_size").long("dict-size").value_name("BYTES").takes_value(true).requires("train_dict")
+            .validator(|s| s.parse::<usize>().map(drop).map_err(|e| e.to_string()))
+            .help("Maximum size of the trained dictionary(default: 112640)"))
+        .arg(Arg::with_name("solid").long("solid")
+            .help("Compresses the entry table and all contents together(builds whole archive on memory)"))
+        .arg(Arg::with_name("deterministic").long("deterministic")
+            .help("Rebuilds the archive once more and fails unless both outputs are identical"))
+        .arg(Arg::with_name("jobs").short("j").long("jobs").value_name("N").takes_value(true)
+            .validator(|s| match s.parse::<usize>() { Ok(n) if n > 0 => Ok(()), _ => Err(format!("invalid number of jobs: {}", s)) })
+            .help("Reads and compresses entries on N threads(the output is the same regardless of N)"))
+        .args(&keys::sealing_args());
+    let keygen_matcher = App::new("keygen").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
+        .about("Generates an Ed25519 key pair(FILE and FILE.pub) for --sign-key/--verify-key")
+        .arg(Arg::with_name("ofile").short("o").long("output").value_name("FILE").required(true).help("Where the secret key will be written"))
+        .arg(Arg::with_name("symmetric").long("symmetric").help("Generates a 32-byte key for --hmac-key or --encrypt-key instead"));
+    let add_matcher = App::new("add").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
+        .about("Adds files to an existing archive(sealed archives are sealed again with the keys)")
+        .arg(Arg::with_name("arc").value_name("FILE").required(true).help("Archive file"))
         .arg(Arg::with_name("ifiled").help("Input File/Directory").required(true).multiple(true))
-        .arg(Arg::with_name("cmethod").short("c").long("compress").value_name("METHOD")
-            .possible_values(&["lz4", "zlib", "zstd11"]).takes_value(true).help("Describes the compression method"));
+        .args(&naming_args())
+        .arg(compression_method_arg())
+        .arg(checksum_method_arg())
+        .args(&keys::resealing_args());
+    let replace_matcher = App::new("replace").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
+        .about("Replaces contents of existing entries with files(sealed archives are sealed again with the keys)")
+        .arg(Arg::with_name("arc").value_name("FILE").required(true).help("Archive file"))
+        .arg(Arg::with_name("ifiled").help("Input File/Directory").required(true).multiple(true))
+        .args(&naming_args())
+        .arg(compression_method_arg())
+        .arg(checksum_method_arg())
+        .args(&keys::resealing_args());
+    let remove_matcher = App::new("remove").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
+        .about("Removes entries from an existing archive(sealed archives are sealed again with the keys)")
+        .arg(Arg::with_name("arc").value_name("FILE").required(true).help("Archive file"))
+        .arg(Arg::with_name("apath").value_name("ASSET_PATH").required(true).multiple(true).help("Entries to be removed"))
+        .arg(compression_method_arg())
+        .arg(checksum_method_arg())
+        .args(&keys::resealing_args());
     let matcher = App::new("peridot-archive").version("0.1").author("S.Percentage <Syn.Tri.Naga@gmail.com>")
-        .subcommands(vec![extract_matcher, create_matcher, ls_matcher]);
+        .subcommands(vec![
+            extract_matcher, create_matcher, ls_matcher, verify_matcher, info_matcher, diff_matcher, mkpatch_matcher,
+            add_matcher, replace_matcher, remove_matcher, keygen_matcher
+        ]);
     let matches = matcher.get_matches();
 
-    if let Some(matches) = matches.subcommand_matches("new") { new(matches); }
-    if let Some(matches) = matches.subcommand_matches("list") { list(matches); }
-    if let Some(matches) = matches.subcommand_matches("extract") { extract(matches); }
+    let result = match matches.subcommand() {
+        ("new", Some(matches)) => new(matches),
+        ("list", Some(matches)) => list(matches),
+        ("verify", Some(matches)) => verify(matches),
+        ("info", Some(matches)) => inspect::info(matches),
+        ("diff", Some(matches)) => inspect::diff(matches),
+        ("mkpatch", Some(matches)) => update::mkpatch(matches),
+        ("extract", Some(matches)) => extract(matches),
+        ("add", Some(matches)) => update::add(matches),
+        ("replace", Some(matches)) => update::replace(matches),
+        ("remove", Some(matches)) => update::remove(matches),
+        ("keygen", Some(matches)) => keys::keygen(matches),
+        _ => Ok(())
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
 }
 
-fn new(args: &ArgMatches) {
-    let directory_walker = args.values_of("ifiled").unwrap()
-        .flat_map(|f| if cfg!(windows) && f.contains('*') {
-            Box::new(glob::glob(f).unwrap().flat_map(|f| extract_directory(&f.unwrap())))
+fn compression_method_arg() -> Arg<'static, 'static> {
+    Arg::with_name("cmethod").short("c").long("compress").value_name("METHOD[:LEVEL]").takes_value(true)
+        .validator(|s| parse_compression_method(&s).map(drop))
+        .help("Describes the compression method(none, lz4, zlib or zstd) and optionally its level(e.g. zstd:19, lz4:hc, zlib:9). \
+            zlib levels choose the LZ77 window(0: stored, 1-8: 128B-16KiB with fixed huffman codes for 1-3, 9: 32KiB)")
+}
+/// Compression method and level(None for the default)
+type CompressionSpec = (par::CompressionMethod, Option<u32>);
+fn compression_method(args: &ArgMatches) -> Option<CompressionSpec> {
+    args.value_of("cmethod").map(|s| parse_compression_method(s).unwrap())
+}
+fn parse_compression_method(s: &str) -> Result<CompressionSpec, String> {
+    let mut parts = s.splitn(2, ':');
+    let (method, max_level) = match parts.next().unwrap() {
+        "none" => (par::CompressionMethod::None, 0),
+        "lz4" => (par::CompressionMethod::Lz4(0), 12),
+        "zlib" => (par::CompressionMethod::Zlib(0), 9),
+        "zstd" => (par::CompressionMethod::Zstd(0), 22),
+        // 以前の指定方法
+        "zstd11" => return Ok((par::CompressionMethod::Zstd(0), Some(11))),
+        m => return Err(format!("unknown compression method: {}", m))
+    };
+    let level = match parts.next() {
+        None => None,
+        Some("hc") if method == par::CompressionMethod::Lz4(0) => Some(9),
+        Some(l) => match l.parse::<u32>() {
+            Ok(l) if l <= max_level && (method != par::CompressionMethod::Zstd(0) || l >= 1) => Some(l),
+            _ => return Err(format!("invalid compression level for {}: {}", s.splitn(2, ':').next().unwrap(), l))
         }
-        else { extract_directory(Path::new(f)) });
-    let compression_method = args.value_of("cmethod").map(|s| match s {
-        "lz4" => par::CompressionMethod::Lz4(0),
-        "zlib" => par::CompressionMethod::Zlib(0),
-        "zstd11" => par::CompressionMethod::Zstd11(0),
-        _ => unreachable!()
-    }).unwrap_or(par::CompressionMethod::None);
-    let mut archive = par::ArchiveWrite::new(compression_method);
-    for f in directory_walker {
-        // println!("input <<= {}", f.display());
-        let fstr = f.to_str().unwrap();
-        if !archive.add(fstr.to_owned(), read(&f).unwrap()) {
-            eprintln!("Warn: {:?} has already been added", fstr);
+    };
+    if method == par::CompressionMethod::None && level.is_some() {
+        return Err("no compression level can be specified for none".to_owned());
+    }
+    return Ok((method, level));
+}
+fn checksum_method_arg() -> Arg<'static, 'static> {
+    Arg::with_name("checksum").long("checksum").value_name("METHOD")
+        .possible_values(&["none", "crc32", "xxh64"]).takes_value(true).help("Describes the checksum method of each entry")
+}
+fn checksum_method(args: &ArgMatches) -> Option<par::ChecksumMethod> {
+    args.value_of("checksum").map(|s| parse_checksum_method(s).unwrap())
+}
+fn parse_checksum_method(s: &str) -> Result<par::ChecksumMethod, String> {
+    match s {
+        "none" => Ok(par::ChecksumMethod::None),
+        "crc32" => Ok(par::ChecksumMethod::CRC32),
+        "xxh64" => Ok(par::ChecksumMethod::XXH64),
+        _ => Err(format!("unknown checksum method: {}", s))
+    }
+}
+fn naming_args() -> [Arg<'static, 'static>; 2] {
+    [
+        Arg::with_name("base_dir").long("base-dir").value_name("DIR").takes_value(true)
+            .help("Records entry names relative to the directory"),
+        Arg::with_name("ext_map").long("map-ext").value_name("FROM=TO").takes_value(true).multiple(true).number_of_values(1)
+            .help("Replaces an extension of entry names(strips the extension if TO is empty)")
+    ]
+}
+/// Expands input files/directories and names them as portable entry names(`/` separated, relative to `--base-dir`)
+fn input_entries(args: &ArgMatches) -> par::Result<Vec<(String, PathBuf)>> {
+    let base = Path::new(args.value_of("base_dir").unwrap_or(""));
+    let ext_map = extension_map(args);
+    input_files(args)?.into_iter().map(|f| {
+        let mut name = par::portable_entry_name(&f, base).ok_or_else(|| IOError::new(ErrorKind::InvalidInput,
+            format!("{} is not under the base directory(specify --base-dir for absolute paths)", f.display())))?;
+        map_extension(&mut name, &ext_map);
+        Ok((name, f))
+    }).collect()
+}
+/// `--map-ext` pairs(FROM, TO)
+fn extension_map<'a>(args: &'a ArgMatches) -> Vec<(&'a str, &'a str)> {
+    args.values_of("ext_map").map_or_else(Vec::new, |vs| vs.map(|v| {
+        let mut p = v.splitn(2, '='); (p.next().unwrap(), p.next().unwrap_or(""))
+    }).collect())
+}
+fn map_extension(name: &mut String, ext_map: &[(&str, &str)]) {
+    let basename_start = name.rfind('/').map_or(0, |p| p + 1);
+    let dot = match name[basename_start..].rfind('.') { Some(p) if p > 0 => basename_start + p, _ => return };
+    if let Some(&(_, to)) = ext_map.iter().find(|&&(from, _)| name[dot + 1..] == *from) {
+        name.truncate(dot);
+        if !to.is_empty() { name.push('.'); name.push_str(to); }
+    }
+}
+/// Expands input files/directories(`ifiled`)
+fn input_files(args: &ArgMatches) -> IOResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for f in args.values_of("ifiled").unwrap() {
+        if cfg!(windows) && f.contains('*') {
+            let paths = glob::glob(f).map_err(|e| IOError::new(ErrorKind::InvalidInput, e.msg))?;
+            for p in paths { extract_directory(&p.map_err(|e| IOError::new(e.error().kind(), e.to_string()))?, &mut files)?; }
+        }
+        else { extract_directory(Path::new(f), &mut files)?; }
+    }
+    return Ok(files);
+}
+
+/// A file to be packed as the entry. `comp` overrides the compression of the archive for this entry
+pub struct InputEntry { name: String, path: PathBuf, comp: Option<CompressionSpec> }
+impl InputEntry {
+    /// The compression method and level of this entry(the level falls back to the archive's one)
+    fn compression<'a>(&'a self, archive: &'a CompressionSpec) -> (&'a par::CompressionMethod, Option<u32>) {
+        match self.comp { Some((ref c, l)) => (c, l.or(archive.1)), None => (&archive.0, archive.1) }
+    }
+}
+fn new(args: &ArgMatches) -> par::Result<()> {
+    let (inputs, defaults) = match args.value_of("manifest") {
+        Some(mpath) => {
+            let mpath = Path::new(mpath);
+            let m = manifest::Manifest::load(mpath)?;
+            (m.entries(mpath.parent().unwrap_or(Path::new("")), &extension_map(args))?, m.archive)
+        },
+        None => (
+            input_entries(args)?.into_iter().map(|(name, path)| InputEntry { name, path, comp: None }).collect(),
+            manifest::ArchiveSettings::default()
+        )
+    };
+    let invalid_setting = |e| IOError::new(ErrorKind::InvalidData, e);
+    let default_comp = match defaults.compress { Some(ref c) => Some(parse_compression_method(c).map_err(invalid_setting)?), None => None };
+    let default_checksum = match defaults.checksum { Some(ref c) => Some(parse_checksum_method(c).map_err(invalid_setting)?), None => None };
+    let comp = compression_method(args).or(default_comp).unwrap_or((par::CompressionMethod::None, None));
+    let dictionary = if args.is_present("train_dict") {
+        // 辞書はzstdでしか使われないので、他の方式の入力があると埋め込んだ分だけ大きくなる
+        if let Some(e) = inputs.iter().find(|e| match *e.compression(&comp).0 { par::CompressionMethod::Zstd(_) => false, _ => true }) {
+            return Err(IOError::new(ErrorKind::InvalidInput,
+                format!("--train-dict requires zstd compression for all entries({} is not compressed with zstd)", e.name)).into());
         }
+        let max_size = args.value_of("dict_size").map_or(112640, |s| s.parse().unwrap());
+        Some(train_dictionary(&inputs, max_size)?)
     }
-    if let Some(ofpath) = args.value_of("ofile") {
-        archive.write(&mut File::create(ofpath).unwrap()).unwrap();
+    else { None };
+    let settings = PackSettings {
+        comp, dictionary,
+        solid: args.is_present("solid") || defaults.solid,
+        checksum: checksum_method(args).or(default_checksum).unwrap_or(par::ChecksumMethod::CRC32),
+        jobs: args.value_of("jobs").map_or(1, |s| s.parse().unwrap())
+    };
+
+    let sealing = keys::sealing_keys(args)?;
+    // 封をする場合はアーカイブ全体をメモリ上に構築してから書き出す
+    if let Some(ofpath) = args.value_of("ofile").filter(|_| sealing.is_none()) {
+        pack(&inputs, &settings, BufWriter::new(File::create(ofpath)?))?.flush()?;
+        if args.is_present("deterministic") {
+            let mut check_path = ofpath.to_owned(); check_path.push_str(".check");
+            pack(&inputs, &settings, BufWriter::new(File::create(&check_path)?))?.flush()?;
+            let same = same_contents(Path::new(ofpath), Path::new(&check_path));
+            remove_file(&check_path)?;
+            if !same? { return Err(not_deterministic_error()); }
+        }
     }
     else {
+        // 標準出力はシークできないので一旦メモリ上に構築する
+        let body = pack(&inputs, &settings, Cursor::new(Vec::new()))?.into_inner();
+        if args.is_present("deterministic") {
+            let rebuilt = pack(&inputs, &settings, Cursor::new(Vec::new()))?.into_inner();
+            if body != rebuilt { return Err(not_deterministic_error()); }
+        }
+        let body = match sealing {
+            Some(k) => { let mut sealed = Vec::new(); k.seal(&body, &mut sealed)?; sealed },
+            None => body
+        };
+        if let Some(ofpath) = args.value_of("ofile") { return File::create(ofpath)?.write_all(&body[..]).map_err(From::from); }
         let foptr = unsafe { libc::fdopen(libc::dup(1), "wb\x00".as_ptr() as *const _) };
-        archive.write(&mut NativeOfstream::from_stream_ptr(foptr).unwrap()).unwrap();
+        NativeOfstream::from_stream_ptr(foptr).ok_or_else(IOError::last_os_error)?.write_all(&body[..])?;
+    }
+    return Ok(());
+}
+struct PackSettings {
+    comp: CompressionSpec, solid: bool, checksum: par::ChecksumMethod, dictionary: Option<Vec<u8>>, jobs: usize
+}
+fn pack<W: Write + Seek>(inputs: &[InputEntry], settings: &PackSettings, mut sink: W) -> par::Result<W> {
+    let (ref comp, level) = settings.comp;
+    if settings.solid {
+        if inputs.iter().any(|e| e.comp.is_some()) {
+            return Err(IOError::new(ErrorKind::InvalidInput, "Solid archives cannot have per-group compression").into());
+        }
+        let mut archive = par::ArchiveWrite::new(comp.clone());
+        if let Some(l) = level { archive = archive.with_compression_level(l); }
+        parallel_map(inputs, settings.jobs, |e| read(&e.path).map_err(From::from), |e, content| {
+            if !archive.add(e.name.clone(), content)? { eprintln!("Warn: {:?} has already been added", e.name); }
+            return Ok(());
+        })?;
+        return archive.write(&mut sink).map(move |_| sink);
+    }
+
+    let mut archive = par::ArchiveStreamWrite::new(sink, comp.clone())?.with_checksum(settings.checksum);
+    if let Some(l) = level { archive = archive.with_compression_level(l); }
+    if let Some(ref d) = settings.dictionary { archive = archive.with_dictionary(d.clone()); }
+    let dictionary = settings.dictionary.as_ref().map(|d| &d[..]);
+    let mut rest = inputs;
+    while !rest.is_empty() {
+        // -j1と大きなファイルはメモリに載せずにストリーミングで圧縮する。
+        // それ以外はメモリ上で並列に圧縮するが、一度に載せる量はjobs * STREAMING_THRESHOLD程度までにする
+        let (mut parallel, mut bytes) = (0, 0);
+        while settings.jobs > 1 && parallel < rest.len() && parallel < settings.jobs * 4 {
+            let size = metadata(&rest[parallel].path)?.len();
+            let chunk_full = parallel > 0 && bytes + size > settings.jobs as u64 * STREAMING_THRESHOLD;
+            if size >= STREAMING_THRESHOLD || chunk_full { break; }
+            bytes += size; parallel += 1;
+        }
+        parallel_map(&rest[..parallel], settings.jobs, |e| {
+            let (c, l) = e.compression(&settings.comp);
+            c.compress_entry(&read(&e.path)?, l, dictionary).map_err(From::from)
+        }, |e, (stored, c)| {
+            if !archive.add_raw(e.name.clone(), &stored, c)? { eprintln!("Warn: {:?} has already been added", e.name); }
+            return Ok(());
+        })?;
+        rest = &rest[parallel..];
+        // 圧縮結果はどちらでも同じなので、スレッド数によらず同じ出力になる
+        if parallel == 0 {
+            let e = &rest[0];
+            let (c, l) = e.compression(&settings.comp);
+            if !archive.add_stream_with(e.name.clone(), File::open(&e.path)?, c, l)? {
+                eprintln!("Warn: {:?} has already been added", e.name);
+            }
+            rest = &rest[1..];
+        }
+    }
+    return archive.finish();
+}
+/// Files of this size or larger are compressed while reading, without loading on memory(even if `-j` is specified)
+const STREAMING_THRESHOLD: u64 = 16 << 20;
+/// Applies `f` to inputs on `jobs` threads, and passes results to `emit` in the order of inputs
+fn parallel_map<T, F, E>(inputs: &[InputEntry], jobs: usize, f: F, mut emit: E) -> par::Result<()>
+        where T: Send, F: Fn(&InputEntry) -> par::Result<T> + Sync, E: FnMut(&InputEntry, T) -> par::Result<()> {
+    // 結果を入力順に書き出すので、メモリに載せておく量を抑えるため少しずつ処理する
+    for chunk in inputs.chunks(jobs * 4) {
+        let mut results: Vec<Option<par::Result<T>>> = chunk.iter().map(|_| None).collect();
+        thread::scope(|s| {
+            let f = &f;
+            let workers: Vec<_> = (0 .. jobs).map(|t| s.spawn(move || {
+                chunk.iter().enumerate().skip(t).step_by(jobs).map(|(i, e)| (i, f(e))).collect::<Vec<_>>()
+            })).collect();
+            for w in workers { for (i, r) in w.join().unwrap() { results[i] = Some(r); } }
+        });
+        for (e, r) in chunk.iter().zip(results) { emit(e, r.unwrap()?)?; }
+    }
+    return Ok(());
+}
+/// Total bytes of samples read for training a dictionary. Files beyond this are not sampled(and the last one is truncated)
+const DICTIONARY_SAMPLE_LIMIT: u64 = 128 << 20;
+fn train_dictionary(inputs: &[InputEntry], max_size: usize) -> par::Result<Vec<u8>> {
+    let (mut samples, mut rest) = (Vec::new(), DICTIONARY_SAMPLE_LIMIT);
+    for e in inputs {
+        if rest == 0 { break; }
+        let mut sample = Vec::new();
+        File::open(&e.path)?.take(rest).read_to_end(&mut sample)?;
+        rest -= sample.len() as u64;
+        samples.push(sample);
+    }
+    return par::train_zstd_dictionary(&samples, max_size).map_err(|e| IOError::new(e.kind(),
+        format!("Training a dictionary failed(too few or too small inputs?): {}", e)).into());
+}
+fn not_deterministic_error() -> par::ArchiveError {
+    IOError::new(ErrorKind::Other, "Archive output is not deterministic: rebuilding from the same inputs produced different bytes").into()
+}
+fn same_contents(a: &Path, b: &Path) -> IOResult<bool> {
+    let (mut fa, mut fb) = (BufReader::new(File::open(a)?), BufReader::new(File::open(b)?));
+    loop {
+        let l = {
+            let (ba, bb) = (fa.fill_buf()?, fb.fill_buf()?);
+            let l = ba.len().min(bb.len());
+            if ba[..l] != bb[..l] { return Ok(false); }
+            if l == 0 { return Ok(ba.len() == bb.len()); }
+            l
+        };
+        fa.consume(l); fb.consume(l);
     }
 }
 
@@ -102,11 +483,15 @@ impl Write for NativeOfstream {
 }
 
 use std::path::{Path, PathBuf}; use std::borrow::ToOwned;
-fn extract_directory(p: &Path) -> Box<Iterator<Item = PathBuf>> {
-    if metadata(p).unwrap().is_dir() {
-        Box::new(read_dir(p).unwrap().flat_map(|f| extract_directory(f.unwrap().path().as_path())))
-    }
-    else {
-        Box::new(Some(p.to_owned()).into_iter())
+/// Appends the file or all files under the directory to `files`
+fn extract_directory(p: &Path, files: &mut Vec<PathBuf>) -> IOResult<()> {
+    let not_found = |e: IOError| IOError::new(e.kind(), format!("{}: {}", p.display(), e));
+    if metadata(p).map_err(not_found)?.is_dir() {
+        // 列挙順はファイルシステム依存なので、毎回同じ順序になるよう名前順に並べる
+        let mut children = read_dir(p).map_err(not_found)?.map(|f| f.map(|f| f.path())).collect::<IOResult<Vec<_>>>()?;
+        children.sort();
+        for f in children { extract_directory(&f, files)?; }
     }
+    else { files.push(p.to_owned()); }
+    return Ok(());
 }
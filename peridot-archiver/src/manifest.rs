@@ -0,0 +1,84 @@
+//! Manifest: declarative description of archive contents(`peridot-archiver new --manifest`)
+//!
+//! ```toml
+//! # Paths are relative to the directory of the manifest file
+//! [archive]
+//! compress = "zstd:19"    # default compression(-c takes precedence)
+//! checksum = "xxh64"
+//!
+//! [[group]]
+//! sources = ["assets/shaders/*.pvp"]  # glob patterns(matched directories are expanded recursively)
+//! base = "assets/shaders"             # entry names are relative to this directory(default: the manifest directory)
+//! dest = "shaders"                    # directory in the archive to place entries under(default: the root)
+//! exclude = ["**/*.tmp"]              # glob patterns of sources to be skipped
+//! compress = "lz4:hc"                 # compression for this group(random-access archives only)
+//! ```
+
+use par;
+use glob;
+use toml;
+use std::fs::read_to_string;
+use std::path::{Path, MAIN_SEPARATOR};
+use std::io::{Error as IOError, ErrorKind};
+use super::{InputEntry, parse_compression_method, map_extension, extract_directory};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)] pub archive: ArchiveSettings,
+    #[serde(default, rename = "group")] groups: Vec<Group>
+}
+/// Defaults of the command line options
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveSettings { pub compress: Option<String>, pub checksum: Option<String>, #[serde(default)] pub solid: bool }
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Group {
+    sources: Vec<String>, base: Option<String>, #[serde(default)] dest: String,
+    #[serde(default)] exclude: Vec<String>, compress: Option<String>
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> par::Result<Self> {
+        let text = read_to_string(path)?;
+        return toml::from_str(&text).map_err(|e| invalid(format!("{}: {}", path.display(), e)).into());
+    }
+    /// Expands groups into files and their entry names(sorted in each group). `dir`: the directory of the manifest
+    pub fn entries(&self, dir: &Path, ext_map: &[(&str, &str)]) -> par::Result<Vec<InputEntry>> {
+        let mut entries = Vec::new();
+        for g in &self.groups {
+            let comp = match g.compress { Some(ref c) => Some(parse_compression_method(c).map_err(invalid)?), None => None };
+            let base = dir.join(g.base.as_ref().map_or("", |s| &s[..]));
+            let exclude = g.exclude.iter().map(|p| glob::Pattern::new(p)).collect::<Result<Vec<_>, _>>()
+                .map_err(|e| invalid(e.msg))?;
+            let dest = g.dest.trim_matches('/');
+
+            let mut files = Vec::new();
+            for s in &g.sources {
+                for p in glob::glob(&source_pattern(dir, s)).map_err(|e| invalid(e.msg))? {
+                    let p = p.map_err(|e| IOError::new(e.error().kind(), e.to_string()))?;
+                    extract_directory(&p, &mut files)?;
+                }
+            }
+            files.sort(); files.dedup();
+            for f in files {
+                // 除外パターンはsourcesと同じくマニフェストからの相対パスに対して適用する
+                if par::portable_entry_name(&f, dir).map_or(false, |n| exclude.iter().any(|p| p.matches(&n))) { continue; }
+                let mut name = par::portable_entry_name(&f, &base).ok_or_else(|| invalid(format!(
+                    "{} is not under the base directory {}", f.display(), base.display())))?;
+                map_extension(&mut name, ext_map);
+                if !dest.is_empty() { name = format!("{}/{}", dest, name); }
+                entries.push(InputEntry { name, path: f, comp: comp.clone() });
+            }
+        }
+        return Ok(entries);
+    }
+}
+/// The pattern relative to `dir`. Characters like `[` or `*` in `dir` are matched literally
+fn source_pattern(dir: &Path, pattern: &str) -> String {
+    if dir.as_os_str().is_empty() || Path::new(pattern).is_absolute() { return pattern.to_owned(); }
+    let dir = glob::Pattern::escape(&dir.to_string_lossy());
+    return if dir.ends_with(MAIN_SEPARATOR) { dir + pattern } else { format!("{}{}{}", dir, MAIN_SEPARATOR, pattern) };
+}
+fn invalid<S: Into<String>>(msg: S) -> IOError { IOError::new(ErrorKind::InvalidData, msg.into()) }
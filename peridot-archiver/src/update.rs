@@ -0,0 +1,182 @@
+//! add/replace/remove: rewriting existing archives, mkpatch: making patch archives
+
+use par;
+use clap::ArgMatches;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::fs::{File, read, rename, remove_file};
+use std::io::{BufWriter, Cursor, Error as IOError, ErrorKind};
+use std::io::prelude::{Write, Seek};
+use std::mem::discriminant;
+use super::{compression_method, checksum_method, input_entries, CompressionSpec};
+use keys;
+use std::collections::BTreeSet;
+
+/// Where the content of an entry in the rewritten archive comes from
+enum EntrySource { Archive, File(PathBuf), Tombstone }
+
+pub fn add(args: &ArgMatches) -> par::Result<()> {
+    let files = input_entries(args)?;
+    rewrite(args, move |entries| {
+        for (name, f) in files {
+            if entries.get(&name).map_or(false, |e| !is_tombstone(e)) {
+                return Err(IOError::new(ErrorKind::AlreadyExists,
+                    format!("{:?} already exists in the archive(use `replace` instead)", name)).into());
+            }
+            entries.insert(name, EntrySource::File(f));
+        }
+        return Ok(());
+    })
+}
+pub fn replace(args: &ArgMatches) -> par::Result<()> {
+    let files = input_entries(args)?;
+    rewrite(args, move |entries| {
+        for (name, f) in files {
+            match entries.get_mut(&name) {
+                Some(ref mut e) if !is_tombstone(e) => **e = EntrySource::File(f),
+                _ => return Err(par::ArchiveError::MissingEntry(name))
+            }
+        }
+        return Ok(());
+    })
+}
+pub fn remove(args: &ArgMatches) -> par::Result<()> {
+    rewrite(args, |entries| {
+        for n in args.values_of("apath").unwrap() {
+            // 墓標を消すとパッチの下のエントリが見えるようになってしまうので、削除対象にしない
+            if !entries.get(n).map_or(false, |e| !is_tombstone(e)) { return Err(par::ArchiveError::MissingEntry(n.to_owned())); }
+            entries.remove(n);
+        }
+        return Ok(());
+    })
+}
+fn is_tombstone(e: &EntrySource) -> bool { match *e { EntrySource::Tombstone => true, _ => false } }
+
+/// Writes entries added or modified from BASE to NEW, and tombstones of entries removed from NEW.
+/// Stacking the patch on BASE(`par::ArchiveSet`) reproduces the entries of NEW.
+/// Sealed archives are opened with `keys::resealing_args`, and the patch is sealed if the sealing keys are given
+pub fn mkpatch(args: &ArgMatches) -> par::Result<()> {
+    let sealing = keys::sealing_keys(args)?;
+    let base = keys::open_archive(args, "arc", false)?;
+    let mut new = keys::open_archive(args, "arc2", false)?;
+    new.set_entry_verification(true);
+
+    let mut entries = BTreeMap::new();
+    let names: BTreeSet<&str> = base.entry_names().chain(new.entry_names()).collect();
+    for n in names {
+        let src = match (base.contains(n), new.contains(n)) {
+            (true, false) => EntrySource::Tombstone,
+            (true, true) if base.read_slice(n)? == new.read_slice(n)? => continue,
+            _ => EntrySource::Archive
+        };
+        entries.insert(n.to_owned(), src);
+    }
+    let mut fp = BufWriter::new(File::create(args.value_of("ofile").unwrap())?);
+    let (comp, checksum) = (compression_method(args), checksum_method(args));
+    match sealing {
+        Some(k) => {
+            let body = write_rewritten(&mut new, entries, comp, checksum, Cursor::new(Vec::new()))?.into_inner();
+            k.seal(&body, &mut fp)?;
+        },
+        None => { write_rewritten(&mut new, entries, comp, checksum, &mut fp)?; }
+    }
+    return fp.flush().map_err(From::from);
+}
+
+/// Edits the entry set of the archive, then replaces the archive atomically(writes to a temporary file and renames it).
+/// Sealed archives are opened with `keys::resealing_args` and sealed again
+fn rewrite<F>(args: &ArgMatches, edit: F) -> par::Result<()>
+        where F: FnOnce(&mut BTreeMap<String, EntrySource>) -> par::Result<()> {
+    let path = Path::new(args.value_of("arc").unwrap());
+    let sealing = keys::sealing_keys(args)?;
+    if sealing.is_none() && args.is_present("verify_key") {
+        return Err(IOError::new(ErrorKind::InvalidInput, "Signed archives must be signed again(specify --sign-key)").into());
+    }
+    if args.is_present("decrypt_key") && !args.is_present("encrypt_key") {
+        return Err(IOError::new(ErrorKind::InvalidInput, "Encrypted archives must be encrypted again(specify --encrypt-key)").into());
+    }
+    let mut archive = keys::open_archive(args, "arc", false)?;
+    // 壊れたエントリをそのまま書き写さない
+    archive.set_entry_verification(true);
+    let mut entries: BTreeMap<_, _> = archive.entry_names().map(|n| (n.to_owned(), EntrySource::Archive)).collect();
+    entries.extend(archive.tombstones().map(|n| (n.to_owned(), EntrySource::Tombstone)));
+    edit(&mut entries)?;
+
+    let mut temp_path = path.as_os_str().to_owned(); temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    let (comp, checksum) = (compression_method(args), checksum_method(args));
+    let written = File::create(&temp_path).map_err(From::from).and_then(|fp| {
+        let mut fp = BufWriter::new(fp);
+        match sealing {
+            Some(k) => {
+                let body = write_rewritten(&mut archive, entries, comp, checksum, Cursor::new(Vec::new()))?.into_inner();
+                k.seal(&body, &mut fp)?;
+            },
+            None => { write_rewritten(&mut archive, entries, comp, checksum, &mut fp)?; }
+        }
+        return fp.flush().map_err(From::from);
+    });
+    // 元のファイルを閉じてから置き換える
+    drop(archive);
+    match written {
+        Ok(_) => rename(&temp_path, path).map_err(From::from),
+        Err(e) => { remove_file(&temp_path).ok(); Err(e) }
+    }
+}
+/// Compression(and checksum) methods and the dictionary of the source archive are preserved unless overrides are specified.
+/// Tombstones can be written only in random-access archives, so the output is random-access if any tombstones are included.
+fn write_rewritten<W: Write + Seek>(archive: &mut par::ArchiveRead, entries: BTreeMap<String, EntrySource>,
+        comp_override: Option<CompressionSpec>, checksum_override: Option<par::ChecksumMethod>, mut sink: W)
+        -> par::Result<W> {
+    let level = comp_override.as_ref().and_then(|c| c.1);
+    if archive.is_random_access() || entries.values().any(is_tombstone) {
+        let comp = comp_override.as_ref().map_or_else(|| if archive.is_random_access() { major_entry_compression_method(archive) }
+            else { archive.compression_method().clone() }, |c| c.0.clone());
+        let checksum = checksum_override.unwrap_or_else(|| if archive.is_random_access() { entry_checksum_method(archive) }
+            else { par::ChecksumMethod::CRC32 });
+        let mut writer = par::ArchiveStreamWrite::new(sink, comp)?.with_checksum(checksum);
+        if let Some(l) = level { writer = writer.with_compression_level(l); }
+        // 辞書で圧縮されたエントリをそのまま移すので、辞書も引き継ぐ
+        if let Some(d) = archive.dictionary() { writer = writer.with_dictionary(d.to_owned()); }
+        for (name, src) in entries {
+            match src {
+                // 再圧縮せずにそのまま移す
+                EntrySource::Archive if comp_override.is_none() && archive.is_random_access() => {
+                    let (stored, c) = archive.read_raw(&name)?;
+                    writer.add_raw(name, &stored, c)?;
+                },
+                EntrySource::Archive => { let b = archive.read_bin(&name)?; writer.add(name, &b)?; },
+                EntrySource::File(p) => { writer.add_stream(name, File::open(p)?)?; },
+                EntrySource::Tombstone => { writer.add_tombstone(name)?; }
+            }
+        }
+        return writer.finish();
+    }
+    else {
+        let comp = comp_override.map_or_else(|| archive.compression_method().clone(), |c| c.0);
+        let mut writer = par::ArchiveWrite::new(comp);
+        if let Some(l) = level { writer = writer.with_compression_level(l); }
+        for (name, src) in entries {
+            let content = match src {
+                EntrySource::File(p) => read(p)?, _ => archive.read_bin(&name)?
+            };
+            writer.add(name, content)?;
+        }
+        writer.write(&mut sink)?;
+    }
+    return Ok(sink);
+}
+/// The checksum method of entries(CRC-32 if the archive is empty)
+fn entry_checksum_method(archive: &par::ArchiveRead) -> par::ChecksumMethod {
+    archive.entry_names().next().map_or(par::ChecksumMethod::CRC32, |n| archive.entry_checksum(n).unwrap().method())
+}
+/// The compression method used by the most entries(applied to newly added entries)
+fn major_entry_compression_method(archive: &par::ArchiveRead) -> par::CompressionMethod {
+    let mut counts: Vec<(par::CompressionMethod, usize)> = Vec::new();
+    for n in archive.entry_names() {
+        let m = archive.entry_compression_method(n).unwrap();
+        if let Some(c) = counts.iter_mut().find(|c| discriminant(&c.0) == discriminant(m)) { c.1 += 1; continue; }
+        counts.push((m.clone(), 1));
+    }
+    return counts.into_iter().max_by_key(|c| c.1).map(|c| c.0).unwrap_or(par::CompressionMethod::None);
+}
@@ -0,0 +1,343 @@
+//! peridot-archiver subcommands run on archives built in a temporary directory
+
+extern crate peridot_archive as par;
+
+use std::fs::{create_dir_all, read, remove_dir_all, write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// A temporary directory removed on drop
+struct Fixture(PathBuf);
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("peridot-archiver-{}-{}", name, std::process::id()));
+        if dir.exists() { remove_dir_all(&dir).unwrap(); }
+        create_dir_all(&dir).unwrap();
+        return Fixture(dir);
+    }
+    fn path(&self, p: &str) -> PathBuf { self.0.join(p) }
+    fn file(&self, p: &str, content: &[u8]) {
+        let path = self.path(p);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, content).unwrap();
+    }
+}
+impl Drop for Fixture { fn drop(&mut self) { remove_dir_all(&self.0).ok(); } }
+
+fn archiver<P: AsRef<Path>>(dir: P, args: &[&str]) -> Output {
+    return Command::new(env!("CARGO_BIN_EXE_peridot-archiver")).current_dir(dir).args(args).output().unwrap();
+}
+fn succeed<P: AsRef<Path>>(dir: P, args: &[&str]) {
+    let out = archiver(dir, args);
+    assert!(out.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&out.stderr));
+}
+
+#[test]
+fn verify_reports_corrupted_entry() {
+    let f = Fixture::new("verify");
+    f.file("in/a.txt", b"intact entry");
+    f.file("in/b.txt", b"entry to be corrupted");
+    succeed(&f.0, &["new", "-o", "v.par", "--base-dir", "in", "in"]);
+    assert!(archiver(&f.0, &["verify", "v.par"]).status.success());
+
+    let mut bytes = read(f.path("v.par")).unwrap();
+    let content = bytes.windows(7).position(|w| w == b"entry t").unwrap();
+    bytes[content] ^= 0x01;
+    write(f.path("v.par"), &bytes).unwrap();
+
+    let verified = archiver(&f.0, &["verify", "v.par"]);
+    let report = String::from_utf8_lossy(&verified.stdout);
+    assert!(!verified.status.success());
+    assert!(report.lines().any(|l| l.starts_with("Corrupted Entry b.txt:")), "{}", report);
+    assert!(!report.contains("Corrupted Entry a.txt"), "{}", report);
+    assert!(report.contains("2 entries: 1 corrupted"), "{}", report);
+}
+
+#[test]
+fn mkpatch_over_base_reproduces_new() {
+    let f = Fixture::new("mkpatch");
+    f.file("base/kept.txt", b"kept");
+    f.file("base/modified.txt", b"before");
+    f.file("base/removed.txt", b"removed");
+    f.file("new/kept.txt", b"kept");
+    f.file("new/modified.txt", b"after");
+    f.file("new/added/x.txt", b"added");
+    succeed(&f.0, &["new", "-o", "base.par", "--base-dir", "base", "base"]);
+    succeed(&f.0, &["new", "-o", "new.par", "--base-dir", "new", "new"]);
+    succeed(&f.0, &["mkpatch", "base.par", "new.par", "-o", "patch.par"]);
+
+    let patch = par::ArchiveRead::map_file(f.path("patch.par"), true).unwrap();
+    assert_eq!(patch.entry_names().collect::<Vec<_>>(), ["added/x.txt", "modified.txt"]);
+    assert_eq!(patch.tombstones().collect::<Vec<_>>(), ["removed.txt"]);
+
+    let set = par::ArchiveSet::open(&[f.path("base.par"), f.path("patch.par")]).unwrap();
+    let new = par::ArchiveRead::map_file(f.path("new.par"), true).unwrap();
+    assert_eq!(set.entry_names().into_iter().collect::<Vec<_>>(), new.entry_names().collect::<Vec<_>>());
+    for n in new.entry_names() { assert_eq!(set.read_slice(n).unwrap(), new.read_slice(n).unwrap(), "{}", n); }
+}
+
+#[test]
+fn mkpatch_opens_and_seals_with_keys() {
+    let f = Fixture::new("mkpatch-sealed");
+    f.file("base/kept.txt", b"kept");
+    f.file("base/removed.txt", b"removed");
+    f.file("new/kept.txt", b"kept");
+    f.file("new/added.txt", b"added");
+    succeed(&f.0, &["keygen", "-o", "sign.key"]);
+    succeed(&f.0, &["new", "-o", "base.par", "--sign-key", "sign.key", "--base-dir", "base", "base"]);
+    succeed(&f.0, &["new", "-o", "new.par", "--sign-key", "sign.key", "--base-dir", "new", "new"]);
+
+    let without_keys = archiver(&f.0, &["mkpatch", "base.par", "new.par", "-o", "patch.par"]);
+    assert!(!without_keys.status.success());
+    assert!(String::from_utf8_lossy(&without_keys.stderr).contains("key is required"));
+
+    succeed(&f.0, &["mkpatch", "base.par", "new.par", "-o", "patch.par", "--verify-key", "sign.key.pub", "--sign-key", "sign.key"]);
+    assert!(par::ArchiveRead::map_file(f.path("patch.par"), false).is_err(), "the patch is not sealed");
+    let keys = par::ArchiveKeys::ed25519(&read(f.path("sign.key.pub")).unwrap()).unwrap();
+    let patch = par::ArchiveRead::open_sealed(f.path("patch.par"), &keys, true).unwrap();
+    assert_eq!(patch.entry_names().collect::<Vec<_>>(), ["added.txt"]);
+    assert_eq!(patch.tombstones().collect::<Vec<_>>(), ["removed.txt"]);
+}
+
+#[test]
+fn output_is_independent_of_jobs() {
+    let f = Fixture::new("jobs");
+    // 読み込みのバッファより大きいものも含める
+    let mut state = 1u32;
+    let noisy: Vec<u8> = (0 .. 200000).map(|i| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        if i % 7 == 0 { (state >> 24) as u8 } else { b"peridot"[i % 7] }
+    }).collect();
+    f.file("in/large.bin", &noisy);
+    f.file("in/empty.txt", b"");
+    for i in 0 .. 20 { f.file(&format!("in/small/{}.txt", i), format!("small entry {}", i).repeat(i + 1).as_bytes()); }
+
+    for comp in &["none", "zlib", "zlib:1", "lz4", "lz4:hc", "zstd", "zstd:3"] {
+        succeed(&f.0, &["new", "-o", "j1.par", "-c", comp, "-j", "1", "--base-dir", "in", "in"]);
+        succeed(&f.0, &["new", "-o", "j4.par", "-c", comp, "-j", "4", "--base-dir", "in", "in"]);
+        assert!(read(f.path("j1.par")).unwrap() == read(f.path("j4.par")).unwrap(), "outputs differ with -c {}", comp);
+    }
+}
+
+#[test]
+fn train_dict_requires_zstd() {
+    let f = Fixture::new("train-dict");
+    for i in 0 .. 64 {
+        f.file(&format!("in/{}.json", i), format!("{{\"name\": \"entry{}\", \"position\": [{}, {}, 0], \"visible\": true}}", i, i * 3, i * 7).as_bytes());
+    }
+    succeed(&f.0, &["new", "-o", "dict.par", "-c", "zstd", "--train-dict", "--base-dir", "in", "in"]);
+    let archive = par::ArchiveRead::map_file(f.path("dict.par"), true).unwrap();
+    assert_eq!(archive.read_slice("5.json").unwrap(), read(f.path("in/5.json")).unwrap());
+
+    for comp in &["lz4", "zlib", "none"] {
+        let out = archiver(&f.0, &["new", "-o", "nodict.par", "-c", comp, "--train-dict", "--base-dir", "in", "in"]);
+        assert!(!out.status.success(), "--train-dict was accepted with -c {}", comp);
+        assert!(String::from_utf8_lossy(&out.stderr).contains("requires zstd"));
+    }
+}
+
+/// Entry names and contents of the archive
+fn contents(archive: &par::ArchiveRead) -> Vec<(String, Vec<u8>)> {
+    archive.entry_names().map(|n| (n.to_owned(), archive.read_slice(n).unwrap().into_owned())).collect()
+}
+
+#[test]
+fn add_replace_remove_keep_the_layout() {
+    let f = Fixture::new("update");
+    for i in 0 .. 64 { f.file(&format!("in/{:02}.txt", i), format!("entry {} of the updated archive", i).as_bytes()); }
+    f.file("added/new.txt", b"added entry");
+    f.file("replaced/00.txt", b"replaced entry");
+    f.file("missing/missing.txt", b"not in the archive");
+    succeed(&f.0, &["new", "-o", "parr.par", "-c", "lz4", "--base-dir", "in", "in"]);
+    succeed(&f.0, &["new", "-o", "solid.par", "-c", "zlib", "--solid", "--base-dir", "in", "in"]);
+    succeed(&f.0, &["new", "-o", "dict.par", "-c", "zstd", "--train-dict", "--base-dir", "in", "in"]);
+
+    for &(arc, random_access, dictionary) in &[("parr.par", true, false), ("solid.par", false, false), ("dict.par", true, true)] {
+        let original = contents(&par::ArchiveRead::map_file(f.path(arc), true).unwrap());
+        succeed(&f.0, &["add", arc, "--base-dir", "added", "added"]);
+        succeed(&f.0, &["replace", arc, "--base-dir", "replaced", "replaced"]);
+        let updated = par::ArchiveRead::map_file(f.path(arc), true).unwrap();
+        assert_eq!(updated.read_slice("new.txt").unwrap(), &b"added entry"[..], "{}", arc);
+        assert_eq!(updated.read_slice("00.txt").unwrap(), &b"replaced entry"[..], "{}", arc);
+        assert_eq!(updated.read_slice("01.txt").unwrap(), &original[1].1[..], "{}", arc);
+        drop(updated);
+
+        assert!(!archiver(&f.0, &["add", arc, "--base-dir", "added", "added"]).status.success(), "{}: added twice", arc);
+        assert!(!archiver(&f.0, &["replace", arc, "--base-dir", "missing", "missing"]).status.success(), "{}: replaced a missing entry", arc);
+        succeed(&f.0, &["remove", arc, "new.txt"]);
+        f.file("replaced/00.txt", &original[0].1);
+        succeed(&f.0, &["replace", arc, "--base-dir", "replaced", "replaced"]);
+        f.file("replaced/00.txt", b"replaced entry");
+
+        let restored = par::ArchiveRead::map_file(f.path(arc), true).unwrap();
+        assert_eq!(contents(&restored), original, "{}", arc);
+        assert_eq!(restored.is_random_access(), random_access, "{}", arc);
+        assert_eq!(restored.dictionary().is_some(), dictionary, "{}", arc);
+    }
+}
+
+#[test]
+fn remove_keeps_tombstones() {
+    let f = Fixture::new("remove-tombstones");
+    f.file("base/a.txt", b"a");
+    f.file("base/b.txt", b"b");
+    f.file("new/a.txt", b"a modified");
+    f.file("new/c.txt", b"c");
+    succeed(&f.0, &["new", "-o", "base.par", "--base-dir", "base", "base"]);
+    succeed(&f.0, &["new", "-o", "new.par", "--base-dir", "new", "new"]);
+    succeed(&f.0, &["mkpatch", "base.par", "new.par", "-o", "patch.par"]);
+
+    // 墓標は削除できず、失敗してもアーカイブは変わらない
+    let before = read(f.path("patch.par")).unwrap();
+    assert!(!archiver(&f.0, &["remove", "patch.par", "b.txt"]).status.success());
+    assert!(read(f.path("patch.par")).unwrap() == before);
+
+    succeed(&f.0, &["remove", "patch.par", "c.txt"]);
+    let patch = par::ArchiveRead::map_file(f.path("patch.par"), true).unwrap();
+    assert_eq!(patch.entry_names().collect::<Vec<_>>(), ["a.txt"]);
+    assert_eq!(patch.tombstones().collect::<Vec<_>>(), ["b.txt"]);
+    let set = par::ArchiveSet::open(&[f.path("base.par"), f.path("patch.par")]).unwrap();
+    assert_eq!(set.entry_names().into_iter().collect::<Vec<_>>(), ["a.txt"]);
+    assert_eq!(set.read_slice("a.txt").unwrap(), &b"a modified"[..]);
+}
+
+#[test]
+fn rewriting_sealed_archive_reseals_it() {
+    let f = Fixture::new("reseal");
+    f.file("in/a.txt", b"a");
+    f.file("added/b.txt", b"b");
+    succeed(&f.0, &["keygen", "-o", "sign.key"]);
+    succeed(&f.0, &["keygen", "--symmetric", "-o", "encrypt.key"]);
+    succeed(&f.0, &["new", "-o", "sealed.par", "--sign-key", "sign.key", "--encrypt-key", "encrypt.key", "--base-dir", "in", "in"]);
+    let before = read(f.path("sealed.par")).unwrap();
+
+    let unsigned = archiver(&f.0, &["add", "sealed.par", "--verify-key", "sign.key.pub", "--decrypt-key", "encrypt.key",
+        "--base-dir", "added", "added"]);
+    assert!(!unsigned.status.success());
+    assert!(String::from_utf8_lossy(&unsigned.stderr).contains("must be signed again"));
+    let unencrypted = archiver(&f.0, &["add", "sealed.par", "--verify-key", "sign.key.pub", "--decrypt-key", "encrypt.key",
+        "--sign-key", "sign.key", "--base-dir", "added", "added"]);
+    assert!(!unencrypted.status.success());
+    assert!(String::from_utf8_lossy(&unencrypted.stderr).contains("must be encrypted again"));
+    assert!(!archiver(&f.0, &["add", "sealed.par", "--base-dir", "added", "added"]).status.success());
+    assert!(read(f.path("sealed.par")).unwrap() == before);
+
+    succeed(&f.0, &["add", "sealed.par", "--verify-key", "sign.key.pub", "--decrypt-key", "encrypt.key",
+        "--sign-key", "sign.key", "--encrypt-key", "encrypt.key", "--base-dir", "added", "added"]);
+    let mut encryption_key = [0u8; 32];
+    encryption_key.copy_from_slice(&read(f.path("encrypt.key")).unwrap());
+    let keys = par::ArchiveKeys::ed25519(&read(f.path("sign.key.pub")).unwrap()).unwrap().with_decryption_key(encryption_key);
+    let archive = par::ArchiveRead::open_sealed(f.path("sealed.par"), &keys, true).unwrap();
+    assert_eq!(contents(&archive), [("a.txt".to_owned(), b"a".to_vec()), ("b.txt".to_owned(), b"b".to_vec())]);
+    // 復号の鍵なしでは開けない
+    let verify_only = par::ArchiveKeys::ed25519(&read(f.path("sign.key.pub")).unwrap()).unwrap();
+    assert!(par::ArchiveRead::open_sealed(f.path("sealed.par"), &verify_only, true).is_err());
+}
+
+#[test]
+fn manifest_groups() {
+    let f = Fixture::new("manifest");
+    // マニフェストのディレクトリ名はパターンとして扱われない(proj1にはマッチしない)
+    f.file("proj[1]/assets/shaders/a.pvp", b"shader");
+    f.file("proj[1]/assets/shaders/b.tmp", b"excluded");
+    f.file("proj[1]/assets/data/x.json", b"{}");
+    f.file("proj1/assets/shaders/decoy.pvp", b"outside the manifest directory");
+    f.file("proj[1]/m.toml", br#"
+[archive]
+compress = "lz4"
+
+[[group]]
+sources = ["assets/shaders/*"]
+base = "assets/shaders"
+dest = "/shaders/"
+exclude = ["**/*.tmp"]
+
+[[group]]
+sources = ["assets/data"]
+base = "assets"
+compress = "zstd:3"
+"#);
+    succeed(&f.0, &["new", "-o", "m.par", "--manifest", "proj[1]/m.toml"]);
+    let archive = par::ArchiveRead::map_file(f.path("m.par"), true).unwrap();
+    assert_eq!(archive.entry_names().collect::<Vec<_>>(), ["data/x.json", "shaders/a.pvp"]);
+    match archive.entry_compression_method("shaders/a.pvp") { Some(&par::CompressionMethod::Lz4(_)) => (), c => panic!("{:?}", c) }
+    match archive.entry_compression_method("data/x.json") { Some(&par::CompressionMethod::Zstd(_)) => (), c => panic!("{:?}", c) }
+    assert_eq!(archive.read_slice("shaders/a.pvp").unwrap(), &b"shader"[..]);
+    // -cはマニフェストの[archive]より優先される(グループごとの指定はそのまま)
+    succeed(&f.0, &["new", "-o", "m.par", "-c", "none", "--manifest", "proj[1]/m.toml"]);
+    let archive = par::ArchiveRead::map_file(f.path("m.par"), true).unwrap();
+    assert_eq!(archive.entry_compression_method("shaders/a.pvp"), Some(&par::CompressionMethod::None));
+    match archive.entry_compression_method("data/x.json") { Some(&par::CompressionMethod::Zstd(_)) => (), c => panic!("{:?}", c) }
+
+    f.file("proj[1]/outside.toml", b"[[group]]\nsources = [\"assets/data\"]\nbase = \"assets/shaders\"\n");
+    let outside = archiver(&f.0, &["new", "-o", "outside.par", "--manifest", "proj[1]/outside.toml"]);
+    assert!(!outside.status.success());
+    assert!(String::from_utf8_lossy(&outside.stderr).contains("is not under the base directory"));
+}
+
+fn stdout_of<P: AsRef<Path>>(dir: P, args: &[&str]) -> String {
+    let out = archiver(dir, args);
+    assert!(out.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&out.stderr));
+    return String::from_utf8(out.stdout).unwrap();
+}
+
+#[test]
+fn info_shows_layout_and_entries() {
+    let f = Fixture::new("info");
+    f.file("in/a.txt", b"aaaa");
+    f.file("in/b.txt", b"bbbbbbbb");
+    succeed(&f.0, &["new", "-o", "solid.par", "-c", "zlib", "--solid", "--base-dir", "in", "in"]);
+    succeed(&f.0, &["new", "-o", "parr.par", "-c", "lz4", "--checksum", "xxh64", "--base-dir", "in", "in"]);
+    succeed(&f.0, &["keygen", "-o", "sign.key"]);
+    succeed(&f.0, &["new", "-o", "sealed.par", "--sign-key", "sign.key", "--base-dir", "in", "in"]);
+
+    let solid = stdout_of(&f.0, &["info", "solid.par"]);
+    assert!(solid.starts_with("Signature: \"pard\"\nCompression: zlib\n"), "{}", solid);
+    assert!(solid.contains("Entries: 2\n"), "{}", solid);
+    assert!(solid.lines().any(|l| l.split_whitespace().eq(["4", "8", "8", "none", "none", "b.txt"].iter().cloned())), "{}", solid);
+
+    let parr = stdout_of(&f.0, &["info", "parr.par"]);
+    assert!(parr.starts_with("Signature: \"parr\" (random access)\nUncompressed Size: 12 bytes\n"), "{}", parr);
+    assert!(!parr.contains("Compression:"), "{}", parr);
+    let a = parr.lines().find(|l| l.ends_with(" a.txt")).unwrap().split_whitespace().collect::<Vec<_>>();
+    assert_eq!((a[0], a[2], a[3]), ("0", "4", "lz4"), "{}", parr);
+    assert!(a[4].starts_with("XXH64:0x"), "{}", parr);
+
+    assert!(!archiver(&f.0, &["info", "sealed.par"]).status.success());
+    let sealed = stdout_of(&f.0, &["info", "sealed.par", "--verify-key", "sign.key.pub"]);
+    assert!(sealed.contains("Entries: 2\n") && sealed.lines().any(|l| l.ends_with(" b.txt")), "{}", sealed);
+}
+
+#[test]
+fn diff_lists_added_removed_and_modified() {
+    let f = Fixture::new("diff");
+    f.file("old/kept.txt", b"kept");
+    f.file("old/modified.txt", b"before");
+    f.file("old/removed.txt", b"removed");
+    f.file("new/kept.txt", b"kept");
+    f.file("new/modified.txt", b"after");
+    f.file("new/added.txt", b"added");
+    succeed(&f.0, &["new", "-o", "old.par", "--base-dir", "old", "old"]);
+    succeed(&f.0, &["new", "-o", "new.par", "-c", "zstd", "--base-dir", "new", "new"]);
+    succeed(&f.0, &["new", "-o", "recompressed.par", "-c", "zlib", "--solid", "--base-dir", "old", "old"]);
+
+    assert_eq!(stdout_of(&f.0, &["diff", "old.par", "new.par"]), "A added.txt\nM modified.txt\nD removed.txt\n");
+    assert_eq!(stdout_of(&f.0, &["diff", "new.par", "old.par"]), "D added.txt\nM modified.txt\nA removed.txt\n");
+    // 圧縮方式や構造が違っても内容が同じなら差分はない
+    assert_eq!(stdout_of(&f.0, &["diff", "old.par", "recompressed.par"]), "");
+}
+
+#[test]
+fn info_to_closed_pipe_exits_quietly() {
+    let f = Fixture::new("info-pipe");
+    // パイプのバッファに収まらない量を出力させる
+    for i in 0 .. 3000 { f.file(&format!("in/{:04}.txt", i), b"x"); }
+    succeed(&f.0, &["new", "-o", "many.par", "--base-dir", "in", "in"]);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_peridot-archiver")).current_dir(&f.0).args(["info", "many.par"])
+        .stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    drop(child.stdout.take());
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(out.stderr.is_empty(), "{}", String::from_utf8_lossy(&out.stderr));
+}
@@ -4,6 +4,7 @@
 extern crate libc;
 extern crate android_logger;
 extern crate bedrock; extern crate peridot_vertex_processing_pack;
+extern crate peridot_archive;
 extern crate android;
 
 use std::ptr::null_mut;
@@ -0,0 +1,81 @@
+//! Asset Loader backed by Peridot Archives(.par)
+
+use super::AssetLoader;
+use peridot_archive::{ArchiveRead, ArchiveSet, EntryBytes, asset_entry_name};
+#[cfg(feature = "archive-security")] use peridot_archive::ArchiveKeys;
+use std::io::{Cursor, Result as IOResult, Error as IOError, ErrorKind};
+use std::path::Path;
+
+/// Serves assets from one or more archives.
+/// Archives opened later take precedence, so patch archives can be stacked on a base archive(see `ArchiveSet`).
+/// Archives are memory-mapped, and uncompressed entries are served without copying.
+pub struct ArchiveAssetLoader {
+    archives: ArchiveSet, prefix: String, verify_entries: bool,
+    #[cfg(feature = "archive-security")] keys: Option<ArchiveKeys>
+}
+impl ArchiveAssetLoader {
+    pub fn new() -> Self {
+        ArchiveAssetLoader {
+            archives: ArchiveSet::new(), prefix: String::new(), verify_entries: false,
+            #[cfg(feature = "archive-security")] keys: None
+        }
+    }
+    /// Accepts only archives sealed with the keys(archives not signed or modified are rejected).
+    /// Must be set before adding archives
+    #[cfg(feature = "archive-security")]
+    pub fn with_keys(mut self, keys: ArchiveKeys) -> Self { self.keys = Some(keys); return self; }
+    /// Opens archives in the order of precedence(lowest first).
+    pub fn open<P: AsRef<Path>>(paths: &[P]) -> IOResult<Self> {
+        let mut this = Self::new();
+        for p in paths { this.add_archive(p)?; }
+        return Ok(this);
+    }
+    pub fn add_archive<P: AsRef<Path>>(&mut self, path: P) -> IOResult<&mut Self> {
+        debug!("Opening Asset Archive: {}...", path.as_ref().display());
+        #[cfg(feature = "archive-security")]
+        let mut a = match self.keys {
+            Some(ref k) => ArchiveRead::open_sealed(path, k, false)?, None => ArchiveRead::map_file(path, false)?
+        };
+        #[cfg(not(feature = "archive-security"))]
+        let mut a = ArchiveRead::map_file(path, false)?;
+        a.set_entry_verification(self.verify_entries);
+        self.archives.push(a);
+        return Ok(self);
+    }
+    /// Validates per-entry checksums of every asset loaded(for devices whose storage may be corrupted).
+    /// Corrupted assets are reported as `InvalidData` errors
+    pub fn set_entry_verification(&mut self, enabled: bool) {
+        self.verify_entries = enabled;
+        self.archives.set_entry_verification(enabled);
+    }
+    /// Sets a directory prefix of entry names(e.g. `assets/` for archives packed from the `assets` directory without `--base-dir`).
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_owned(); return self;
+    }
+
+    /// Maps an asset path(`shaders.pass`) and an extension(`pvp`) onto an entry name(`shaders/pass.pvp`).
+    pub fn entry_name(&self, path: &str, ext: &str) -> String {
+        let mut name = self.prefix.clone();
+        name.push_str(&asset_entry_name(path, ext));
+        return name;
+    }
+    fn read_entry(&self, path: &str, ext: &str) -> IOResult<EntryBytes> {
+        let name = self.entry_name(path, ext);
+        debug!("Loading Asset: {} from archives...", name);
+        return match self.archives.resolve(&name) {
+            Some(a) => a.read_shared(&name).map_err(From::from),
+            None => Err(IOError::new(ErrorKind::NotFound, format!("Asset not found in archives: {}", name)))
+        };
+    }
+}
+impl AssetLoader for ArchiveAssetLoader {
+    type Asset = Cursor<EntryBytes>;
+    type StreamingAsset = Cursor<EntryBytes>;
+
+    fn get(&self, path: &str, ext: &str) -> IOResult<Cursor<EntryBytes>> {
+        self.read_entry(path, ext).map(Cursor::new)
+    }
+    fn get_streaming(&self, path: &str, ext: &str) -> IOResult<Cursor<EntryBytes>> {
+        self.read_entry(path, ext).map(Cursor::new)
+    }
+}
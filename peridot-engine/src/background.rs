@@ -0,0 +1,102 @@
+//! Background Asset Loading
+
+use std::io::{Result as IOResult, Error as IOError, ErrorKind};
+use std::sync::mpsc::{channel, Sender, Receiver, TryRecvError};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::any::Any;
+use std::thread::{self, JoinHandle};
+
+/// An asset being loaded on the worker thread(`Engine::load_background`). Poll it in `EngineEvents::update`
+pub struct AssetLoading<A>(Option<Receiver<IOResult<A>>>);
+impl<A> AssetLoading<A> {
+    /// Takes the result if loading has completed. None while in progress(and after the result is taken)
+    pub fn poll(&mut self) -> Option<IOResult<A>> {
+        let r = match self.0.as_ref()?.try_recv() {
+            Ok(r) => r,
+            Err(TryRecvError::Empty) => return None,
+            // 結果を送る前にジョブが終わった(ワーカーが停止した)場合だけ
+            Err(TryRecvError::Disconnected) => Err(worker_stopped())
+        };
+        // 結果を送ったSenderは破棄されるので、以降はDisconnectedを見ないようにする
+        self.0 = None;
+        return Some(r);
+    }
+    /// Blocks until loading completes. Fails if the result has already been taken by `poll`
+    pub fn wait(self) -> IOResult<A> {
+        match self.0 {
+            Some(r) => r.recv().unwrap_or_else(|_| Err(worker_stopped())),
+            None => Err(IOError::new(ErrorKind::Other, "The result has already been taken"))
+        }
+    }
+}
+fn worker_stopped() -> IOError { IOError::new(ErrorKind::Other, "Background asset loader has stopped") }
+fn job_panicked(payload: Box<dyn Any + Send>) -> IOError {
+    let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned()).unwrap_or_else(|| "unknown reason".to_owned());
+    return IOError::new(ErrorKind::Other, format!("Loading panicked: {}", message));
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+/// Runs loading jobs in the order requested on a thread
+pub(crate) struct BackgroundLoader { queue: Option<Sender<Job>>, worker: Option<JoinHandle<()>> }
+impl BackgroundLoader {
+    pub fn new() -> Self {
+        let (queue, jobs) = channel::<Job>();
+        let worker = thread::Builder::new().name("Peridot Asset Loader".to_owned())
+            .spawn(move || for j in jobs { j(); }).expect("Spawning the background asset loader");
+        return BackgroundLoader { queue: Some(queue), worker: Some(worker) };
+    }
+    pub fn run<T, F>(&self, job: F) -> AssetLoading<T> where T: Send + 'static, F: FnOnce() -> IOResult<T> + Send + 'static {
+        let (sender, receiver) = channel();
+        // パニックはその読み込みの失敗として返し、ワーカーは止めない
+        let job = move || { sender.send(catch_unwind(AssertUnwindSafe(job)).unwrap_or_else(|p| Err(job_panicked(p)))).ok(); };
+        // 送れなかった場合はReceiver側で停止済みとして扱われる
+        self.queue.as_ref().unwrap().send(Box::new(job)).ok();
+        return AssetLoading(Some(receiver));
+    }
+}
+impl Drop for BackgroundLoader {
+    fn drop(&mut self) {
+        // 受付を閉じて、残っている読み込みが終わるのを待つ
+        drop(self.queue.take());
+        if let Some(w) = self.worker.take() { w.join().ok(); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poll_after_completion_returns_none() {
+        let loader = BackgroundLoader::new();
+        let (start, started) = channel::<()>();
+        let mut loading = loader.run(move || { started.recv().ok(); Ok(42) });
+        assert!(loading.poll().is_none());
+        start.send(()).unwrap();
+        let r = loop { if let Some(r) = loading.poll() { break r; } thread::yield_now(); };
+        assert_eq!(r.unwrap(), 42);
+        // 結果を受け取った後は何度pollしてもNone
+        for _ in 0 .. 3 { assert!(loading.poll().is_none()); }
+        assert!(loading.wait().is_err());
+    }
+    #[test]
+    fn failed_job_is_reported_once() {
+        let loader = BackgroundLoader::new();
+        let mut loading = loader.run(|| -> IOResult<()> { Err(IOError::new(ErrorKind::NotFound, "missing")) });
+        let r = loop { if let Some(r) = loading.poll() { break r; } thread::yield_now(); };
+        assert_eq!(r.unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(loading.poll().is_none());
+    }
+    #[test]
+    fn panicked_job_fails_only_its_loading() {
+        let loader = BackgroundLoader::new();
+        let panicked = loader.run(|| -> IOResult<()> { panic!("loading failed") });
+        let next = loader.run(|| Ok("loaded after the panic"));
+        let e = panicked.wait().unwrap_err();
+        assert!(e.to_string().contains("loading failed"), "{}", e);
+        // ワーカーは動き続けている
+        assert_eq!(next.wait().unwrap(), "loaded after the panic");
+        assert_eq!(loader.run(|| Ok(1)).wait().unwrap(), 1);
+    }
+}
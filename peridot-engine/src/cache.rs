@@ -0,0 +1,144 @@
+//! Asset Caching
+
+use super::FromAsset;
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::Result as IOResult;
+use std::rc::Rc;
+
+/// Counters of `AssetCache`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AssetCacheStats {
+    /// Loads served from the cache
+    pub hits: usize,
+    /// Loads which read and parsed the asset
+    pub misses: usize,
+    /// Entries dropped by `evict_unused`/`invalidate`/`clear`
+    pub evictions: usize,
+    /// Entries currently cached
+    pub entries: usize
+}
+
+/// Shares loaded assets by (path, type) through reference-counted handles(`Engine::load_cached`).
+/// Entries stay cached until evicted, even if all handles are dropped
+pub struct AssetCache { entries: RefCell<HashMap<(String, TypeId), Rc<dyn Any>>>, stats: Cell<AssetCacheStats> }
+impl AssetCache {
+    pub fn new() -> Self { AssetCache { entries: RefCell::new(HashMap::new()), stats: Cell::new(AssetCacheStats::default()) } }
+    /// Returns the cached asset, or caches the result of `load`
+    pub fn get_or_load<A: FromAsset + 'static, F: FnOnce() -> IOResult<A>>(&self, path: &str, load: F) -> IOResult<Rc<A>> {
+        let key = (path.to_owned(), TypeId::of::<A>());
+        if let Some(a) = self.entries.borrow().get(&key) {
+            self.update_stats(|s| s.hits += 1);
+            return Ok(a.clone().downcast().unwrap());
+        }
+        // 読み込み中に他のアセットがキャッシュされてもいいように、借用を解いてから読む
+        let a = Rc::new(load()?);
+        self.entries.borrow_mut().insert(key, a.clone());
+        self.update_stats(|s| s.misses += 1);
+        return Ok(a);
+    }
+    /// Drops entries not referenced by any handle. return -> the number of entries dropped
+    pub fn evict_unused(&self) -> usize {
+        let mut entries = self.entries.borrow_mut();
+        let count = entries.len();
+        entries.retain(|_, a| Rc::strong_count(a) > 1);
+        let evicted = count - entries.len();
+        self.update_stats(|s| s.evictions += evicted);
+        return evicted;
+    }
+    /// Drops entries of the path(of any type) so that the next load reads it again. Handles already given out are kept alive
+    pub fn invalidate(&self, path: &str) {
+        let mut entries = self.entries.borrow_mut();
+        let count = entries.len();
+        entries.retain(|k, _| k.0 != path);
+        let evicted = count - entries.len();
+        self.update_stats(|s| s.evictions += evicted);
+    }
+    pub fn clear(&self) {
+        let evicted = self.entries.borrow().len();
+        self.entries.borrow_mut().clear();
+        self.update_stats(|s| s.evictions += evicted);
+    }
+    pub fn stats(&self) -> AssetCacheStats {
+        AssetCacheStats { entries: self.entries.borrow().len(), .. self.stats.get() }
+    }
+
+    fn update_stats<F: FnOnce(&mut AssetCacheStats)>(&self, f: F) {
+        let mut s = self.stats.get(); f(&mut s); self.stats.set(s);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use LogicalAssetData;
+    use std::io::{Read, Seek, Cursor};
+
+    #[derive(Debug, PartialEq)]
+    struct Text(String);
+    impl LogicalAssetData for Text { fn ext() -> &'static str { "txt" } }
+    impl FromAsset for Text {
+        fn from_asset<Asset: Read + Seek>(mut asset: Asset) -> IOResult<Self> {
+            let mut s = String::new(); asset.read_to_string(&mut s).map(|_| Text(s))
+        }
+    }
+    #[derive(Debug, PartialEq)]
+    struct Length(u64);
+    impl LogicalAssetData for Length { fn ext() -> &'static str { "txt" } }
+    impl FromAsset for Length {
+        fn from_asset<Asset: Read + Seek>(mut asset: Asset) -> IOResult<Self> {
+            asset.seek(::std::io::SeekFrom::End(0)).map(Length)
+        }
+    }
+    fn load<A: FromAsset>(content: &str) -> IOResult<A> { A::from_asset(Cursor::new(content.as_bytes().to_vec())) }
+
+    #[test]
+    fn handles_are_shared() {
+        let cache = AssetCache::new();
+        let a = cache.get_or_load("a", || load::<Text>("alpha")).unwrap();
+        let a2 = cache.get_or_load("a", || -> IOResult<Text> { panic!("loaded twice") }).unwrap();
+        let b = cache.get_or_load("b", || load::<Text>("beta")).unwrap();
+        assert!(Rc::ptr_eq(&a, &a2));
+        assert!(!Rc::ptr_eq(&a, &b));
+        assert_eq!(*a, Text("alpha".to_owned()));
+        assert_eq!(cache.stats(), AssetCacheStats { hits: 1, misses: 2, evictions: 0, entries: 2 });
+    }
+    #[test]
+    fn failed_loads_are_not_cached() {
+        let cache = AssetCache::new();
+        let e = cache.get_or_load("a", || -> IOResult<Text> { Err(::std::io::ErrorKind::NotFound.into()) });
+        assert_eq!(e.unwrap_err().kind(), ::std::io::ErrorKind::NotFound);
+        assert_eq!(cache.stats(), AssetCacheStats::default());
+        assert_eq!(*cache.get_or_load("a", || load::<Text>("alpha")).unwrap(), Text("alpha".to_owned()));
+    }
+    #[test]
+    fn unused_entries_are_evicted_after_the_last_handle_drops() {
+        let cache = AssetCache::new();
+        let a = cache.get_or_load("a", || load::<Text>("alpha")).unwrap();
+        let a2 = cache.get_or_load("a", || load::<Text>("alpha")).unwrap();
+        drop(cache.get_or_load("b", || load::<Text>("beta")).unwrap());
+        assert_eq!(cache.evict_unused(), 1);
+        drop(a);
+        assert_eq!(cache.evict_unused(), 0, "a handle is still alive");
+        drop(a2);
+        assert_eq!(cache.evict_unused(), 1);
+        assert_eq!(cache.stats(), AssetCacheStats { hits: 1, misses: 2, evictions: 2, entries: 0 });
+        // 追い出された後は読み直す
+        cache.get_or_load("a", || load::<Text>("reloaded")).unwrap();
+        assert_eq!(cache.stats().misses, 3);
+    }
+    #[test]
+    fn types_are_cached_separately() {
+        let cache = AssetCache::new();
+        let text = cache.get_or_load("a", || load::<Text>("alpha")).unwrap();
+        let length = cache.get_or_load("a", || load::<Length>("alpha")).unwrap();
+        assert_eq!((&*text, &*length), (&Text("alpha".to_owned()), &Length(5)));
+        assert_eq!(cache.stats(), AssetCacheStats { hits: 0, misses: 2, evictions: 0, entries: 2 });
+        assert!(Rc::ptr_eq(&length, &cache.get_or_load("a", || load::<Length>("")).unwrap()));
+
+        // 型によらずパスのエントリがすべて捨てられる。渡したハンドルは生きている
+        cache.invalidate("a");
+        assert_eq!(cache.stats(), AssetCacheStats { hits: 1, misses: 2, evictions: 2, entries: 0 });
+        assert_eq!(*text, Text("alpha".to_owned()));
+    }
+}
@@ -0,0 +1,44 @@
+//! Asset Hot-reloading(debug builds only)
+
+use super::{AssetLoader, FromAsset};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::io::Result as IOResult;
+use std::time::{Duration, Instant, SystemTime};
+
+/// ファイルの更新日時を調べる間隔
+const POLLING_INTERVAL: Duration = Duration::from_millis(500);
+
+struct WatchedAsset<AL> { path: String, ext: &'static str, modified: Option<SystemTime>, reload: fn(&AL, &str) -> IOResult<Box<Any>> }
+/// Polls modified times of watched assets(`Engine::watch_asset`) and loads them again when they change
+pub(crate) struct AssetWatcher<AL: AssetLoader> { assets: RefCell<Vec<WatchedAsset<AL>>>, last_polled: Cell<Instant> }
+impl<AL: AssetLoader> AssetWatcher<AL> {
+    pub fn new() -> Self { AssetWatcher { assets: RefCell::new(Vec::new()), last_polled: Cell::new(Instant::now()) } }
+    pub fn watch<A: FromAsset + 'static>(&self, loader: &AL, path: &str) {
+        let modified = loader.modified_time(path, A::ext());
+        if modified.is_none() { warn!("Asset {} cannot be watched(the loader does not provide modified times)", path); }
+        self.assets.borrow_mut().push(WatchedAsset { path: path.to_owned(), ext: A::ext(), modified, reload: reload::<AL, A> });
+    }
+    /// return -> (path, reloaded asset) of modified assets
+    pub fn poll(&self, loader: &AL) -> Vec<(String, Box<Any>)> {
+        if self.last_polled.get().elapsed() < POLLING_INTERVAL { return Vec::new(); }
+        self.last_polled.set(Instant::now());
+
+        let mut reloaded = Vec::new();
+        for a in self.assets.borrow_mut().iter_mut() {
+            let modified = loader.modified_time(&a.path, a.ext);
+            if modified.is_none() || modified == a.modified { continue; }
+            // 失敗しても次に更新されるまでは読み直さない(書き込み途中だった場合は次の更新で拾われる)
+            a.modified = modified;
+            info!("Reloading Asset: {}...", a.path);
+            match (a.reload)(loader, &a.path) {
+                Ok(v) => reloaded.push((a.path.clone(), v)),
+                Err(e) => warn!("Reloading Asset {} failed: {}", a.path, e)
+            }
+        }
+        return reloaded;
+    }
+}
+fn reload<AL: AssetLoader, A: FromAsset + 'static>(loader: &AL, path: &str) -> IOResult<Box<Any>> {
+    loader.get(path, A::ext()).and_then(A::from_asset).map(|a| Box::new(a) as Box<Any>)
+}
@@ -5,12 +5,17 @@ extern crate pathfinder_partitioner;
 
 use bedrock as br; use bedrock::traits::*;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 
 mod window; use self::window::WindowRenderTargets;
 pub use self::window::{PlatformRenderTarget, SurfaceInfo};
 mod resource; pub use self::resource::*;
+mod archive; pub use self::archive::*;
+mod background; pub use self::background::*; use self::background::BackgroundLoader;
+mod cache; pub use self::cache::*;
+#[cfg(debug_assertions)] mod hotreload; #[cfg(debug_assertions)] use self::hotreload::AssetWatcher;
 #[cfg(debug_assertions)] mod debug; #[cfg(debug_assertions)] use self::debug::DebugReport;
 pub mod utils; pub use self::utils::*;
 
@@ -20,16 +25,23 @@ pub trait EngineEvents<AL: AssetLoader, PRT: PlatformRenderTarget> : Sized {
     fn update(&mut self, _e: &Engine<Self, AL, PRT>, _on_backbuffer_of: u32) -> (Option<br::SubmissionBatch>, br::SubmissionBatch) {
         (None, br::SubmissionBatch::default())
     }
+    /// (debug builds) Called before `update` when an asset watched by `Engine::watch_asset` is modified and loaded again.
+    /// `asset` is the reloaded value of the type watched
+    fn asset_reloaded(&mut self, _e: &Engine<Self, AL, PRT>, _path: &str, _asset: Box<Any>) {}
 }
 impl<AL: AssetLoader, PRT: PlatformRenderTarget> EngineEvents<AL, PRT> for () { fn init(_e: &Engine<Self, AL, PRT>) -> Self { () } }
 
 use std::io::{Read, Seek, Result as IOResult, BufReader};
+use std::time::SystemTime;
+use std::any::Any;
 pub trait AssetLoader {
     type Asset: Read + Seek;
     type StreamingAsset: Read;
 
     fn get(&self, path: &str, ext: &str) -> IOResult<Self::Asset>;
     fn get_streaming(&self, path: &str, ext: &str) -> IOResult<Self::StreamingAsset>;
+    /// Last modified time of the asset for hot-reloading(`Engine::watch_asset`). None if the loader cannot tell(e.g. archives)
+    fn modified_time(&self, _path: &str, _ext: &str) -> Option<SystemTime> { None }
 }
 pub trait LogicalAssetData: Sized {
     fn ext() -> &'static str;
@@ -52,7 +64,8 @@ mod input; pub use self::input::*;
 
 pub struct Engine<E: EngineEvents<AL, PRT>, AL: AssetLoader, PRT: PlatformRenderTarget> {
     prt: PRT, surface: SurfaceInfo, wrt: WindowRenderTargets,
-    pub(self) g: Graphics, event_handler: Option<RefCell<E>>, asset_loader: AL, ip: Rc<InputProcess>
+    pub(self) g: Graphics, event_handler: Option<RefCell<E>>, asset_loader: Arc<AL>, ip: Rc<InputProcess>,
+    background: BackgroundLoader, cache: AssetCache, #[cfg(debug_assertions)] watcher: AssetWatcher<AL>
 }
 impl<E: EngineEvents<AL, PRT>, AL: AssetLoader, PRT: PlatformRenderTarget> Engine<E, AL, PRT> {
     pub fn launch<IPP: InputProcessPlugin>(name: &str, version: (u32, u32, u32), prt: PRT, asset_loader: AL, ipp: &mut IPP)
@@ -61,7 +74,10 @@ impl<E: EngineEvents<AL, PRT>, AL: AssetLoader, PRT: PlatformRenderTarget> Engin
         let surface = prt.create_surface(&g.instance, &g.adapter, g.graphics_queue.family)?;
         trace!("Creating WindowRenderTargets...");
         let wrt = WindowRenderTargets::new(&g, &surface, &prt)?;
-        let mut this = Engine { g, surface, wrt, event_handler: None, asset_loader, prt, ip: InputProcess::new().into() };
+        let mut this = Engine {
+            g, surface, wrt, event_handler: None, asset_loader: Arc::new(asset_loader), prt, ip: InputProcess::new().into(),
+            background: BackgroundLoader::new(), cache: AssetCache::new(), #[cfg(debug_assertions)] watcher: AssetWatcher::new()
+        };
         trace!("Initializing Game...");
         let eh = E::init(&this);
         this.event_handler = Some(eh.into());
@@ -75,6 +91,26 @@ impl<E: EngineEvents<AL, PRT>, AL: AssetLoader, PRT: PlatformRenderTarget> Engin
     pub fn streaming<A: FromStreamingAsset>(&self, path: &str) -> IOResult<A> {
         self.asset_loader.get_streaming(path, A::ext()).and_then(A::from_asset)
     }
+    /// Same as `load`, but shares the asset already loaded from the path(see `AssetCache`)
+    pub fn load_cached<A: FromAsset + 'static>(&self, path: &str) -> IOResult<Rc<A>> {
+        self.cache.get_or_load(path, || self.load(path))
+    }
+    pub fn asset_cache(&self) -> &AssetCache { &self.cache }
+    /// Loads the asset on the worker thread without blocking the frame. Requests are processed in order
+    pub fn load_background<A: FromAsset + Send + 'static>(&self, path: &str) -> AssetLoading<A> where AL: Send + Sync + 'static {
+        let (loader, path) = (self.asset_loader.clone(), path.to_owned());
+        self.background.run(move || loader.get(&path, A::ext()).and_then(A::from_asset))
+    }
+    /// `streaming` on the worker thread
+    pub fn streaming_background<A: FromStreamingAsset + Send + 'static>(&self, path: &str) -> AssetLoading<A>
+            where AL: Send + Sync + 'static {
+        let (loader, path) = (self.asset_loader.clone(), path.to_owned());
+        self.background.run(move || loader.get_streaming(&path, A::ext()).and_then(A::from_asset))
+    }
+    /// Loads the asset again whenever its file is modified, and passes it to `EngineEvents::asset_reloaded`(debug builds only).
+    /// Effective with loaders which provide `AssetLoader::modified_time`
+    #[cfg(debug_assertions)]
+    pub fn watch_asset<A: FromAsset + 'static>(&self, path: &str) { self.watcher.watch::<A>(&self.asset_loader, path); }
 
     pub fn graphics(&self) -> &Graphics { &self.g }
     pub fn graphics_device(&self) -> &br::Device { &self.g.device }
@@ -101,6 +137,11 @@ impl<E: EngineEvents<AL, PRT>, AL: AssetLoader, PRT: PlatformRenderTarget> Engin
         self.ip.prepare_for_frame();
         {
             let mut eh_mut = self.event_handler.as_ref().unwrap().borrow_mut();
+            #[cfg(debug_assertions)]
+            for (path, asset) in self.watcher.poll(&self.asset_loader) {
+                self.cache.invalidate(&path);
+                eh_mut.asset_reloaded(self, &path, asset);
+            }
             let (copy_submission, mut fb_submission) = eh_mut.update(self, bb_index);
             if let Some(mut cs) = copy_submission {
                 // copy -> render
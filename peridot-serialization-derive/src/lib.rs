@@ -0,0 +1,141 @@
+//! `#[derive(PeridotSerialize, PeridotDeserialize)]` for the traits in `peridot-serialization-utils`
+//!
+//! Fields are written in the declaration order with their own encodings.
+//! Enums lead the index of the variant(in the declaration order, not the discriminant value) as `VariableUInt`.
+//! Expanded code refers `::peridot_serialization_utils`, so it must be a dependency of the crate deriving.
+//!
+//! Unions are rejected:
+//!
+//! ```compile_fail
+//! #[macro_use] extern crate peridot_serialization_derive;
+//! extern crate peridot_serialization_utils;
+//!
+//! #[derive(PeridotSerialize)]
+//! union Bits { i: u32, f: f32 }
+//! # fn main() {}
+//! ```
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use] extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{parse_macro_input, DeriveInput, Data, Fields, Generics, GenericParam, Ident};
+use syn::spanned::Spanned;
+
+#[proc_macro_derive(PeridotSerialize)]
+pub fn derive_serialize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = bound_type_params(input.generics.clone(), quote!(::peridot_serialization_utils::PeridotSerialize));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match input.data {
+        Data::Struct(ref s) => {
+            let (pattern, writes) = write_fields(&s.fields);
+            quote! { let mut written = 0; let #name #pattern = *self; #writes }
+        },
+        Data::Enum(ref e) => {
+            let arms = e.variants.iter().enumerate().map(|(n, v)| {
+                let (variant, index) = (&v.ident, n as u32);
+                let (pattern, writes) = write_fields(&v.fields);
+                quote! {
+                    #name::#variant #pattern => {
+                        written += ::peridot_serialization_utils::VariableUInt(#index).write(writer)?;
+                        #writes
+                    }
+                }
+            });
+            quote! { let mut written = 0; match *self { #(#arms),* } }
+        },
+        Data::Union(ref u) => return unsupported(u.union_token.span(), "PeridotSerialize")
+    };
+    let expanded = quote! {
+        impl #impl_generics ::peridot_serialization_utils::PeridotSerialize for #name #ty_generics #where_clause {
+            #[allow(unused_mut)]
+            fn serialize<__W: ::std::io::Write>(&self, writer: &mut __W) -> ::std::io::Result<usize> {
+                #body
+                #[allow(unreachable_code)] return Ok(written);
+            }
+        }
+    };
+    return expanded.into();
+}
+
+#[proc_macro_derive(PeridotDeserialize)]
+pub fn derive_deserialize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = bound_type_params(input.generics.clone(), quote!(::peridot_serialization_utils::PeridotDeserialize));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match input.data {
+        Data::Struct(ref s) => {
+            let construct = read_fields(quote!(#name), &s.fields);
+            quote! { Ok(#construct) }
+        },
+        Data::Enum(ref e) => {
+            let arms = e.variants.iter().enumerate().map(|(n, v)| {
+                let (variant, index) = (&v.ident, n as u32);
+                let construct = read_fields(quote!(#name::#variant), &v.fields);
+                quote! { #index => Ok(#construct) }
+            });
+            let unknown = format!("Unknown variant index of {}: {{}}", name);
+            quote! {
+                let ::peridot_serialization_utils::VariableUInt(index) = ::peridot_serialization_utils::VariableUInt::read(reader)?;
+                match index {
+                    #(#arms,)*
+                    _ => Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData, format!(#unknown, index)))
+                }
+            }
+        },
+        Data::Union(ref u) => return unsupported(u.union_token.span(), "PeridotDeserialize")
+    };
+    let expanded = quote! {
+        impl #impl_generics ::peridot_serialization_utils::PeridotDeserialize for #name #ty_generics #where_clause {
+            fn deserialize<__R: ::std::io::BufRead>(reader: &mut __R) -> ::std::io::Result<Self> { #body }
+        }
+    };
+    return expanded.into();
+}
+
+/// Adds the trait bound to all type parameters
+fn bound_type_params(mut generics: Generics, bound: TokenStream2) -> Generics {
+    for p in generics.params.iter_mut() {
+        if let GenericParam::Type(ref mut t) = *p { t.bounds.push(syn::parse2(bound.clone()).unwrap()); }
+    }
+    return generics;
+}
+/// return -> (pattern binding fields by reference, statements adding written bytes to `written`)
+fn write_fields(fields: &Fields) -> (TokenStream2, TokenStream2) {
+    let bindings: Vec<_> = (0 .. fields.iter().count()).map(|n| Ident::new(&format!("__f{}", n), proc_macro2::Span::call_site())).collect();
+    let pattern = match *fields {
+        Fields::Named(_) => {
+            let names = fields.iter().map(|f| f.ident.as_ref().unwrap());
+            quote! { { #(#names: ref #bindings),* } }
+        },
+        Fields::Unnamed(_) => quote! { ( #(ref #bindings),* ) },
+        Fields::Unit => quote!()
+    };
+    let writes = quote! {
+        #(written += ::peridot_serialization_utils::PeridotSerialize::serialize(#bindings, writer)?;)*
+    };
+    return (pattern, writes);
+}
+/// return -> expression constructing `path` with fields read in order
+fn read_fields(path: TokenStream2, fields: &Fields) -> TokenStream2 {
+    let reads = fields.iter().map(|_| quote!(::peridot_serialization_utils::PeridotDeserialize::deserialize(reader)?));
+    match *fields {
+        Fields::Named(_) => {
+            let names = fields.iter().map(|f| f.ident.as_ref().unwrap());
+            quote! { #path { #(#names: #reads),* } }
+        },
+        Fields::Unnamed(_) => quote! { #path( #(#reads),* ) },
+        Fields::Unit => path
+    }
+}
+fn unsupported(span: proc_macro2::Span, name: &str) -> TokenStream {
+    syn::Error::new(span, format!("{} cannot be derived for unions", name)).to_compile_error().into()
+}
@@ -0,0 +1,63 @@
+#[macro_use] extern crate peridot_serialization_derive;
+extern crate peridot_serialization_utils;
+use peridot_serialization_utils::*;
+use std::fmt::Debug;
+use std::io::Cursor;
+
+#[derive(PeridotSerialize, PeridotDeserialize, Debug, PartialEq)]
+struct Named { id: u32, name: String, tags: Vec<String>, parent: Option<Box<Named>>, weight: f32 }
+#[derive(PeridotSerialize, PeridotDeserialize, Debug, PartialEq)]
+struct Tuple(u8, i32, U64LE);
+#[derive(PeridotSerialize, PeridotDeserialize, Debug, PartialEq)]
+struct Unit;
+#[derive(PeridotSerialize, PeridotDeserialize, Debug, PartialEq)]
+struct Generic<T> { values: Vec<T>, last: Option<T> }
+#[derive(PeridotSerialize, PeridotDeserialize, Debug, PartialEq)]
+enum Shape { Empty, Point(i16, i16), Polygon { closed: bool, points: Vec<u32> } }
+
+/// return -> written bytes
+fn round_trip<T: PeridotSerialize + PeridotDeserialize + Debug + PartialEq>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    assert_eq!(value.serialize(&mut bytes).unwrap(), bytes.len());
+    let mut reader = Cursor::new(&bytes[..]);
+    assert_eq!(&T::deserialize(&mut reader).unwrap(), value);
+    assert_eq!(reader.position() as usize, bytes.len(), "trailing bytes left for {:?}", value);
+    return bytes;
+}
+
+#[test]
+fn structs_round_trip() {
+    let child = Named { id: 1, name: "child".to_owned(), tags: vec![], parent: None, weight: 0.5 };
+    round_trip(&child);
+    round_trip(&Named {
+        id: 300, name: "ペリドット".to_owned(), tags: vec!["a".to_owned(), String::new()],
+        parent: Some(Box::new(child)), weight: -2.0
+    });
+    round_trip(&Tuple(0xff, -1, U64LE(0x0102030405060708)));
+    assert!(round_trip(&Unit).is_empty());
+    round_trip(&Generic { values: vec![1u64, 1 << 40], last: Some(3) });
+    round_trip(&Generic::<String> { values: vec![], last: None });
+}
+#[test]
+fn fields_are_written_in_declaration_order() {
+    // 各フィールドのエンコーディングをそのまま並べたものになる
+    let bytes = round_trip(&Tuple(7, -2, U64LE(1)));
+    assert_eq!(bytes, [7, 3, 1, 0, 0, 0, 0, 0, 0, 0]);
+    let bytes = round_trip(&Named { id: 128, name: "ab".to_owned(), tags: vec!["c".to_owned()], parent: None, weight: 1.5 });
+    assert_eq!(bytes, [0x80, 0x01, 2, b'a', b'b', 1, 1, b'c', 0, 0x00, 0x00, 0xc0, 0x3f]);
+}
+#[test]
+fn enums_lead_variant_index() {
+    assert_eq!(round_trip(&Shape::Empty), [0]);
+    assert_eq!(round_trip(&Shape::Point(1, -1)), [1, 2, 1]);
+    assert_eq!(round_trip(&Shape::Polygon { closed: true, points: vec![5, 6] }), [2, 1, 2, 5, 6]);
+    round_trip(&vec![Shape::Empty, Shape::Polygon { closed: false, points: vec![] }]);
+}
+#[test]
+fn invalid_input_is_rejected() {
+    let e = Shape::deserialize(&mut Cursor::new(&[3u8][..])).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    assert!(Shape::deserialize(&mut Cursor::new(&[1u8, 2][..])).is_err());
+    assert!(Named::deserialize(&mut Cursor::new(&[1u8, 5, b'a'][..])).is_err());
+    assert!(Generic::<u8>::deserialize(&mut Cursor::new(&[2u8, 0, 0, 2][..])).is_err());
+}
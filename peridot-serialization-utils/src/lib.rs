@@ -1,45 +1,118 @@
-use std::io::prelude::{BufRead, Write};
-use std::io::{Result as IOResult, Error as IOError, ErrorKind};
+use std::io::prelude::{BufRead, Read, Write};
+use std::io::{Result as IOResult, Error as IOError, ErrorKind, Seek, SeekFrom};
+use std::convert::TryFrom;
+use std::cmp::min;
 use std::str::from_utf8;
 
 /// octet variadic unsigned integer
 pub struct VariableUInt(pub u32);
 impl VariableUInt {
+    pub fn write<W: Write>(&self, writer: &mut W) -> IOResult<usize> { write_fragments(writer, self.0 as _) }
+    /// Fails with `InvalidData` if the value exceeds u32(more than 5 bytes or overflowing bits)
+    pub fn read<R: BufRead>(reader: &mut R) -> IOResult<Self> {
+        read_fragments(reader, 5, u32::max_value() as _, "VariableUInt").map(|v| VariableUInt(v as _))
+    }
+}
+/// 64-bit version of `VariableUInt`. Values within u32 have the same representation as `VariableUInt`
+pub struct VariableUInt64(pub u64);
+impl VariableUInt64 {
+    pub fn write<W: Write>(&self, writer: &mut W) -> IOResult<usize> { write_fragments(writer, self.0) }
+    /// Fails with `InvalidData` if the value exceeds u64(more than 10 bytes or overflowing bits)
+    pub fn read<R: BufRead>(reader: &mut R) -> IOResult<Self> {
+        read_fragments(reader, 10, u64::max_value(), "VariableUInt64").map(VariableUInt64)
+    }
+}
+/// octet variadic signed integer(zigzag encoded: 0, -1, 1, -2, ... are mapped onto 0, 1, 2, 3, ...)
+pub struct VariableInt(pub i32);
+impl VariableInt {
     pub fn write<W: Write>(&self, writer: &mut W) -> IOResult<usize> {
-        let mut iteration_count = 0;
-        Self::iter_fragment(self.0, |v| { iteration_count += 1; writer.write_all(&[v]) }).map(|_| iteration_count)
+        VariableUInt(((self.0 << 1) ^ (self.0 >> 31)) as _).write(writer)
     }
-    /// u32 to break apart into bytes, and calls closure with fragment
-    fn iter_fragment<CB: FnMut(u8) -> IOResult<()>>(v: u32, mut callback: CB) -> IOResult<()> {
-        let (n7, nr) = ((v & 0x7f) as u8, v >> 7);
-        callback(n7 | if nr != 0 { 0x80 } else { 0 })?;
-        if nr != 0 { Self::iter_fragment(nr, callback) } else { Ok(()) }
+    pub fn read<R: BufRead>(reader: &mut R) -> IOResult<Self> {
+        VariableUInt::read(reader).map(|VariableUInt(v)| VariableInt((v >> 1) as i32 ^ -((v & 1) as i32)))
+    }
+}
+/// 64-bit version of `VariableInt`
+pub struct VariableInt64(pub i64);
+impl VariableInt64 {
+    pub fn write<W: Write>(&self, writer: &mut W) -> IOResult<usize> {
+        VariableUInt64(((self.0 << 1) ^ (self.0 >> 63)) as _).write(writer)
     }
     pub fn read<R: BufRead>(reader: &mut R) -> IOResult<Self> {
-        let (mut v, mut shifts) = (0u32, 0usize);
-        loop {
-            let (consumed, done) = {
-                let mut available = match reader.fill_buf() {
-                    Ok(v) => v,
-                    Err(e) => if e.kind() == ErrorKind::Interrupted { continue; } else { return Err(e); }
-                };
-                let (mut consumed, mut done) = (0, false);
-                while !available.is_empty() {
-                    v |= ((available[0] & 0x7f) as u32) << shifts;
-                    shifts += 7;
-                    consumed += 1;
-                    if (available[0] & 0x80) == 0 { done = true; break; }
-                    available = &available[1..];
-                }
-                (consumed, done)
+        VariableUInt64::read(reader).map(|VariableUInt64(v)| VariableInt64((v >> 1) as i64 ^ -((v & 1) as i64)))
+    }
+}
+
+/// breaks the value apart into 7-bit fragments(lower first, MSB set on all but the last). return -> written bytes
+fn write_fragments<W: Write>(writer: &mut W, mut v: u64) -> IOResult<usize> {
+    let mut written = 0;
+    loop {
+        let (n7, nr) = ((v & 0x7f) as u8, v >> 7);
+        writer.write_all(&[n7 | if nr != 0 { 0x80 } else { 0 }])?;
+        written += 1;
+        if nr == 0 { return Ok(written); }
+        v = nr;
+    }
+}
+/// reads fragments written by `write_fragments`.
+/// Fails with `InvalidData` if fragments continue over `max_bytes` or the value exceeds `max_value`
+fn read_fragments<R: BufRead>(reader: &mut R, max_bytes: usize, max_value: u64, type_name: &str) -> IOResult<u64> {
+    let (mut v, mut count) = (0u64, 0usize);
+    loop {
+        let (consumed, done) = {
+            let mut available = match reader.fill_buf() {
+                Ok(v) => v,
+                Err(e) => if e.kind() == ErrorKind::Interrupted { continue; } else { return Err(e); }
             };
-            reader.consume(consumed);
-            if done { return Ok(VariableUInt(v)); }
+            if available.is_empty() { return Err(IOError::new(ErrorKind::UnexpectedEof, format!("Unexpected EOF in {}", type_name))); }
+            let (mut consumed, mut done) = (0, false);
+            while !available.is_empty() {
+                if count >= max_bytes { return Err(overflow_error(type_name)); }
+                let (fragment, shifts) = ((available[0] & 0x7f) as u64, 7 * count as u32);
+                // 上位のビットがはみ出す場合もあふれとする
+                if (fragment << shifts) >> shifts != fragment { return Err(overflow_error(type_name)); }
+                v |= fragment << shifts;
+                count += 1;
+                consumed += 1;
+                if (available[0] & 0x80) == 0 { done = true; break; }
+                available = &available[1..];
+            }
+            (consumed, done)
+        };
+        reader.consume(consumed);
+        if done {
+            return if v <= max_value { Ok(v) } else { Err(overflow_error(type_name)) };
         }
     }
 }
+fn overflow_error(type_name: &str) -> IOError {
+    IOError::new(ErrorKind::InvalidData, format!("{} overflows(too many continuation bytes)", type_name))
+}
 
-/// a utf-8 string representation leading its byte length as `VariableUInt`.
+/// little-endian fixed-width values, readable on hosts of any endianness
+macro_rules! LittleEndianCodec {
+    ($($name: ident($t: ty; $bytes: expr)),*) => {$(
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub struct $name(pub $t);
+        impl $name {
+            pub fn write<W: Write>(&self, writer: &mut W) -> IOResult<usize> { writer.write_all(&self.to_bytes()).map(|_| $bytes) }
+            pub fn read<R: Read>(reader: &mut R) -> IOResult<Self> {
+                let mut bytes = [0u8; $bytes]; reader.read_exact(&mut bytes).map(|_| Self::from_bytes(bytes))
+            }
+            pub fn to_bytes(&self) -> [u8; $bytes] { self.0.to_le_bytes() }
+            pub fn from_bytes(bytes: [u8; $bytes]) -> Self { $name(<$t>::from_le_bytes(bytes)) }
+        }
+        impl PeridotSerialize for $name {
+            fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> { self.write(writer) }
+        }
+        impl PeridotDeserialize for $name {
+            fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> { Self::read(reader) }
+        }
+    )*}
+}
+LittleEndianCodec!(U16LE(u16; 2), U32LE(u32; 4), U64LE(u64; 8), F32LE(f32; 4));
+
+/// a utf-8 string representation leading its byte length as `VariableUInt64`(the same bytes as `VariableUInt` within u32).
 pub struct PascalString(pub String);
 pub struct PascalStr<'s>(pub &'s str);
 impl PascalString {
@@ -47,15 +120,197 @@ impl PascalString {
         PascalStr(&self.0).write(writer)
     }
     pub fn read<R: BufRead>(reader: &mut R) -> IOResult<Self> {
-        let VariableUInt(bytelength) = VariableUInt::read(reader)?;
-        let mut bytes = Vec::with_capacity(bytelength as _); unsafe { bytes.set_len(bytelength as _); }
-        reader.read_exact(&mut bytes).map(drop)?;
-        return from_utf8(&bytes[..]).map(|s| PascalString(s.to_owned())).map_err(|e| IOError::new(ErrorKind::Other, e));
+        let VariableUInt64(bytelength) = VariableUInt64::read(reader)?;
+        let bytes = read_bytes(reader, bytelength)?;
+        return String::from_utf8(bytes).map(PascalString).map_err(|e| IOError::new(ErrorKind::Other, e));
     }
 }
 impl<'s> PascalStr<'s> {
     pub fn write<W: Write>(&self, writer: &mut W) -> IOResult<usize> {
-        VariableUInt(self.0.as_bytes().len() as _).write(writer)
+        VariableUInt64(self.0.as_bytes().len() as _).write(writer)
             .and_then(|wl| writer.write_all(self.0.as_bytes()).map(move |_| wl + self.0.as_bytes().len()))
     }
+    /// Borrows the string from the input without copying
+    pub fn read(reader: &mut SliceReader<'s>) -> IOResult<Self> {
+        let PascalBytes(bytes) = PascalBytes::read(reader)?;
+        return from_utf8(bytes).map(PascalStr).map_err(|e| IOError::new(ErrorKind::Other, e));
+    }
+}
+/// a byte blob leading its length as `VariableUInt64`(the same layout as `PascalStr`)
+pub struct PascalBytes<'s>(pub &'s [u8]);
+impl<'s> PascalBytes<'s> {
+    pub fn write<W: Write>(&self, writer: &mut W) -> IOResult<usize> {
+        VariableUInt64(self.0.len() as _).write(writer)
+            .and_then(|wl| writer.write_all(self.0).map(move |_| wl + self.0.len()))
+    }
+    /// Borrows the blob from the input without copying
+    pub fn read(reader: &mut SliceReader<'s>) -> IOResult<Self> {
+        let VariableUInt64(length) = VariableUInt64::read(reader)?;
+        return reader.read_borrowed(length).map(PascalBytes);
+    }
+}
+
+/// A reader over bytes on memory(e.g. a memory-mapped file) which can lend parts of the input(`PascalStr::read`,
+/// `PascalBytes::read`) instead of copying them. Works as a `BufRead + Seek` for other readers
+pub struct SliceReader<'d> { data: &'d [u8], position: u64 }
+impl<'d> SliceReader<'d> {
+    pub fn new(data: &'d [u8]) -> Self { SliceReader { data, position: 0 } }
+    pub fn position(&self) -> u64 { self.position }
+    /// Whole input
+    pub fn get_ref(&self) -> &'d [u8] { self.data }
+    /// Input after the current position
+    pub fn remaining(&self) -> &'d [u8] { &self.data[min(self.position, self.data.len() as u64) as usize..] }
+    /// Borrows next `length` bytes and advances. Fails with `UnexpectedEof` if the input is shorter
+    pub fn read_borrowed(&mut self, length: u64) -> IOResult<&'d [u8]> {
+        let remaining = self.remaining();
+        if length > remaining.len() as u64 {
+            return Err(IOError::new(ErrorKind::UnexpectedEof, format!("Input ended before {} bytes recorded", length)));
+        }
+        self.position += length;
+        return Ok(&remaining[..length as usize]);
+    }
+}
+impl<'d> Read for SliceReader<'d> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let n = min(buf.len(), self.remaining().len());
+        buf[..n].copy_from_slice(&self.remaining()[..n]);
+        self.position += n as u64;
+        return Ok(n);
+    }
+}
+impl<'d> BufRead for SliceReader<'d> {
+    fn fill_buf(&mut self) -> IOResult<&[u8]> { Ok(self.remaining()) }
+    fn consume(&mut self, amt: usize) { self.position += amt as u64; }
+}
+impl<'d> Seek for SliceReader<'d> {
+    fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
+        let position = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => offset_position(self.data.len() as u64, d),
+            SeekFrom::Current(d) => offset_position(self.position, d)
+        };
+        self.position = position.ok_or_else(|| IOError::new(ErrorKind::InvalidInput, "Seeking to an invalid position"))?;
+        return Ok(self.position);
+    }
+}
+fn offset_position(base: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 { base.checked_add(offset as u64) } else { base.checked_sub(offset.wrapping_neg() as u64) }
+}
+
+/// 入力に記録された長さを信用して一度に確保する量の上限
+const PREALLOCATION_LIMIT: u64 = 64 * 1024;
+/// Reads exactly `length` bytes. Memory grows as the input is actually read,
+/// so a corrupted length fails with `UnexpectedEof` instead of allocating at once
+pub fn read_bytes<R: Read>(reader: &mut R, length: u64) -> IOResult<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(capacity_hint(length));
+    reader.take(length).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < length {
+        return Err(IOError::new(ErrorKind::UnexpectedEof, format!("Input ended before {} bytes recorded", length)));
+    }
+    return Ok(bytes);
+}
+/// Capacity to reserve for `count` elements recorded in input(bounded, elements beyond this are reserved as they are read)
+pub fn capacity_hint(count: u64) -> usize { min(count, PREALLOCATION_LIMIT) as _ }
+
+/// Types which can be written in the binary formats of this crate(`#[derive(PeridotSerialize)]` in `peridot-serialization-derive`).
+/// Integers are written as varints(`u8`/`i8` and floats as they are, in little endian),
+/// strings as `PascalString`, `Vec`s leading their length as `VariableUInt64`, and `Option`s leading a 0/1 byte
+pub trait PeridotSerialize {
+    /// return -> written bytes
+    fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize>;
+}
+/// Types which can be read from what `PeridotSerialize` writes
+pub trait PeridotDeserialize: Sized {
+    fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self>;
+}
+
+macro_rules! VarintSerialization {
+    ($($t: ty => $v: ident($vt: ty)),*) => {$(
+        impl PeridotSerialize for $t {
+            fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> { $v(*self as $vt).write(writer) }
+        }
+        impl PeridotDeserialize for $t {
+            fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> {
+                let $v(v) = $v::read(reader)?;
+                <$t>::try_from(v).map_err(|_| IOError::new(ErrorKind::InvalidData, concat!("Value out of range of ", stringify!($t))))
+            }
+        }
+    )*}
+}
+VarintSerialization!(u16 => VariableUInt(u32), u32 => VariableUInt(u32), u64 => VariableUInt64(u64),
+    usize => VariableUInt64(u64), i16 => VariableInt(i32), i32 => VariableInt(i32), i64 => VariableInt64(i64));
+impl PeridotSerialize for u8 {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> { writer.write_all(&[*self]).map(|_| 1) }
+}
+impl PeridotDeserialize for u8 {
+    fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> { let mut b = [0u8; 1]; reader.read_exact(&mut b).map(|_| b[0]) }
+}
+impl PeridotSerialize for i8 {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> { (*self as u8).serialize(writer) }
+}
+impl PeridotDeserialize for i8 {
+    fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> { u8::deserialize(reader).map(|v| v as _) }
+}
+impl PeridotSerialize for bool {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> { (*self as u8).serialize(writer) }
+}
+impl PeridotDeserialize for bool {
+    fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> {
+        match u8::deserialize(reader)? {
+            0 => Ok(false), 1 => Ok(true),
+            v => Err(IOError::new(ErrorKind::InvalidData, format!("Invalid bool value: {}", v)))
+        }
+    }
+}
+impl PeridotSerialize for f32 {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> { F32LE(*self).write(writer) }
+}
+impl PeridotDeserialize for f32 {
+    fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> { F32LE::read(reader).map(|F32LE(v)| v) }
+}
+impl PeridotSerialize for f64 {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> { U64LE(self.to_bits()).write(writer) }
+}
+impl PeridotDeserialize for f64 {
+    fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> { U64LE::read(reader).map(|U64LE(v)| f64::from_bits(v)) }
+}
+impl PeridotSerialize for String {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> { PascalStr(self).write(writer) }
+}
+impl PeridotDeserialize for String {
+    fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> { PascalString::read(reader).map(|PascalString(s)| s) }
+}
+impl<T: PeridotSerialize> PeridotSerialize for Vec<T> {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> {
+        let mut written = VariableUInt64(self.len() as _).write(writer)?;
+        for x in self { written += x.serialize(writer)?; }
+        return Ok(written);
+    }
+}
+impl<T: PeridotDeserialize> PeridotDeserialize for Vec<T> {
+    fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> {
+        let VariableUInt64(count) = VariableUInt64::read(reader)?;
+        let mut xs = Vec::with_capacity(capacity_hint(count));
+        for _ in 0 .. count { xs.push(T::deserialize(reader)?); }
+        return Ok(xs);
+    }
+}
+impl<T: PeridotSerialize> PeridotSerialize for Option<T> {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> {
+        match *self {
+            Some(ref v) => true.serialize(writer).and_then(|w0| v.serialize(writer).map(move |w1| w0 + w1)),
+            None => false.serialize(writer)
+        }
+    }
+}
+impl<T: PeridotDeserialize> PeridotDeserialize for Option<T> {
+    fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> {
+        if bool::deserialize(reader)? { T::deserialize(reader).map(Some) } else { Ok(None) }
+    }
+}
+impl<T: PeridotSerialize> PeridotSerialize for Box<T> {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IOResult<usize> { (**self).serialize(writer) }
+}
+impl<T: PeridotDeserialize> PeridotDeserialize for Box<T> {
+    fn deserialize<R: BufRead>(reader: &mut R) -> IOResult<Self> { T::deserialize(reader).map(Box::new) }
 }
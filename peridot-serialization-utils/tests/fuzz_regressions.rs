@@ -0,0 +1,26 @@
+//! Inputs in the fuzzing corpus(`fuzz/corpus/serialization`) named `regression_*` must be rejected
+
+extern crate peridot_serialization_utils;
+use peridot_serialization_utils::*;
+use std::fs::{read, read_dir};
+use std::io::Cursor;
+use std::path::Path;
+
+#[test]
+fn serialization_corpus() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("../fuzz/corpus/serialization");
+    for f in read_dir(corpus).unwrap() {
+        let path = f.unwrap().path();
+        if !path.file_name().unwrap().to_string_lossy().starts_with("regression_") { continue; }
+        let bytes = read(&path).unwrap();
+        assert!(PascalString::read(&mut Cursor::new(&bytes)).is_err(), "{}", path.display());
+    }
+}
+#[test]
+fn huge_lengths_are_not_allocated() {
+    let claimed = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, b'a'];
+    assert!(PascalString::read(&mut Cursor::new(&claimed[..])).is_err());
+    assert!(Vec::<u64>::deserialize(&mut Cursor::new(&claimed[..])).is_err());
+    assert!(read_bytes(&mut Cursor::new(&b"abc"[..]), 1 << 62).is_err());
+    assert_eq!(read_bytes(&mut Cursor::new(&b"abc"[..]), 2).unwrap(), b"ab");
+}
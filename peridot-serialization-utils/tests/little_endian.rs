@@ -0,0 +1,35 @@
+extern crate peridot_serialization_utils;
+use peridot_serialization_utils::*;
+use std::io::Cursor;
+
+#[test]
+fn little_endian_layouts() {
+    let mut bytes = Vec::new();
+    assert_eq!(U16LE(0x0102).write(&mut bytes).unwrap(), 2);
+    assert_eq!(U32LE(0x01020304).write(&mut bytes).unwrap(), 4);
+    assert_eq!(U64LE(0x0102030405060708).write(&mut bytes).unwrap(), 8);
+    assert_eq!(F32LE(1.5).write(&mut bytes).unwrap(), 4);
+    assert_eq!(bytes, [
+        0x02, 0x01,
+        0x04, 0x03, 0x02, 0x01,
+        0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01,
+        0x00, 0x00, 0xc0, 0x3f
+    ]);
+
+    let mut reader = Cursor::new(bytes);
+    assert_eq!(U16LE::read(&mut reader).unwrap(), U16LE(0x0102));
+    assert_eq!(U32LE::read(&mut reader).unwrap(), U32LE(0x01020304));
+    assert_eq!(U64LE::read(&mut reader).unwrap(), U64LE(0x0102030405060708));
+    assert_eq!(F32LE::read(&mut reader).unwrap(), F32LE(1.5));
+    assert!(U16LE::read(&mut reader).is_err());
+}
+#[test]
+fn little_endian_bytes() {
+    assert_eq!(U32LE(0xdeadbeef).to_bytes(), [0xef, 0xbe, 0xad, 0xde]);
+    assert_eq!(U64LE::from_bytes([1, 0, 0, 0, 0, 0, 0, 0x80]), U64LE(0x8000000000000001));
+    assert_eq!(F32LE::from_bytes([0, 0, 0x80, 0xbf]), F32LE(-1.0));
+}
+#[test]
+fn truncated_input() {
+    assert!(U64LE::read(&mut Cursor::new(&[1u8, 2, 3][..])).is_err());
+}
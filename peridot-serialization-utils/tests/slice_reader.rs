@@ -0,0 +1,40 @@
+extern crate peridot_serialization_utils;
+use peridot_serialization_utils::*;
+use std::io::{Seek, SeekFrom};
+
+#[test]
+fn borrowed_strings_and_blobs() {
+    let mut bytes = Vec::new();
+    PascalStr("ほげ").write(&mut bytes).unwrap();
+    PascalBytes(&[1, 2, 3]).write(&mut bytes).unwrap();
+    VariableUInt64(300).write(&mut bytes).unwrap();
+    assert_eq!(&bytes[..8], &[6, 0xe3, 0x81, 0xbb, 0xe3, 0x81, 0x92, 3]);
+
+    let mut reader = SliceReader::new(&bytes);
+    let PascalStr(s) = PascalStr::read(&mut reader).unwrap();
+    let PascalBytes(b) = PascalBytes::read(&mut reader).unwrap();
+    assert_eq!((s, b), ("ほげ", &[1u8, 2, 3][..]));
+    // 借用した部分は入力そのもの
+    assert_eq!(s.as_ptr(), bytes[1..].as_ptr());
+    assert_eq!(VariableUInt64::read(&mut reader).unwrap().0, 300);
+    assert!(reader.remaining().is_empty());
+}
+#[test]
+fn truncated_borrows() {
+    let bytes = [5u8, b'a', b'b'];
+    let mut reader = SliceReader::new(&bytes);
+    assert!(PascalStr::read(&mut reader).is_err());
+    assert!(PascalBytes::read(&mut SliceReader::new(&[0xff, 0xff, 0xff, 0xff, 0x0f])).is_err());
+    assert!(PascalStr::read(&mut SliceReader::new(&[2, 0xc3, 0x28])).is_err());
+}
+#[test]
+fn seeking() {
+    let bytes = [1u8, 2, 3, 4];
+    let mut reader = SliceReader::new(&bytes);
+    assert_eq!(reader.seek(SeekFrom::End(-1)).unwrap(), 3);
+    assert_eq!(reader.read_borrowed(1).unwrap(), &[4]);
+    assert!(reader.seek(SeekFrom::Current(-5)).is_err());
+    assert_eq!(reader.seek(SeekFrom::Start(10)).unwrap(), 10);
+    assert!(reader.remaining().is_empty());
+    assert!(reader.read_borrowed(1).is_err());
+}
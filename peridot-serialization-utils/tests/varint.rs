@@ -0,0 +1,73 @@
+//! Variable-length integers at their boundaries, and rejection of overlong or overflowing encodings
+
+extern crate peridot_serialization_utils;
+use peridot_serialization_utils::*;
+use std::io::{Cursor, ErrorKind};
+
+fn bytes<F: FnOnce(&mut Vec<u8>) -> std::io::Result<usize>>(write: F) -> Vec<u8> {
+    let mut sink = Vec::new();
+    let written = write(&mut sink).unwrap();
+    assert_eq!(written, sink.len());
+    return sink;
+}
+
+#[test]
+fn unsigned_boundaries() {
+    for &(v, encoded) in &[
+        (0u64, &[0x00][..]), (0x7f, &[0x7f][..]), (0x80, &[0x80, 0x01][..]), (0x3fff, &[0xff, 0x7f][..]),
+        (u32::max_value() as u64, &[0xff, 0xff, 0xff, 0xff, 0x0f][..]),
+        (u64::max_value(), &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01][..])
+    ] {
+        assert_eq!(bytes(|w| VariableUInt64(v).write(w)), encoded, "{}", v);
+        assert_eq!(VariableUInt64::read(&mut Cursor::new(encoded)).unwrap().0, v);
+        if v <= u32::max_value() as u64 {
+            // 32bit版と同じ表現
+            assert_eq!(bytes(|w| VariableUInt(v as u32).write(w)), encoded, "{}", v);
+            assert_eq!(VariableUInt::read(&mut Cursor::new(encoded)).unwrap().0 as u64, v);
+        }
+    }
+}
+#[test]
+fn zigzag_boundaries() {
+    for &(v, zigzag) in &[
+        (0i64, 0u64), (-1, 1), (1, 2), (-2, 3), (i64::max_value(), u64::max_value() - 1), (i64::min_value(), u64::max_value())
+    ] {
+        let encoded = bytes(|w| VariableInt64(v).write(w));
+        assert_eq!(encoded, bytes(|w| VariableUInt64(zigzag).write(w)), "{}", v);
+        assert_eq!(VariableInt64::read(&mut Cursor::new(&encoded)).unwrap().0, v);
+    }
+    for &(v, zigzag) in &[(0i32, 0u32), (-1, 1), (1, 2), (i32::max_value(), u32::max_value() - 1), (i32::min_value(), u32::max_value())] {
+        let encoded = bytes(|w| VariableInt(v).write(w));
+        assert_eq!(encoded, bytes(|w| VariableUInt(zigzag).write(w)), "{}", v);
+        assert_eq!(VariableInt::read(&mut Cursor::new(&encoded)).unwrap().0, v);
+    }
+}
+#[test]
+fn overlong_or_overflowing_values_are_rejected() {
+    let invalid = |r: std::io::Result<u64>, input: &[u8]| match r {
+        Err(ref e) if e.kind() == ErrorKind::InvalidData => (),
+        r => panic!("{:?} must be rejected: {:?}", input, r.map_err(|e| e.kind()))
+    };
+    // 継続ビットが続きすぎる
+    let overlong64 = [0x80; 11];
+    invalid(VariableUInt64::read(&mut Cursor::new(&overlong64[..])).map(|v| v.0), &overlong64);
+    let overlong32 = [0x80, 0x80, 0x80, 0x80, 0x80, 0x00];
+    invalid(VariableUInt::read(&mut Cursor::new(&overlong32[..])).map(|v| v.0 as u64), &overlong32);
+    invalid(VariableInt::read(&mut Cursor::new(&overlong32[..])).map(|v| v.0 as u64), &overlong32);
+    // 最後のバイトの上位ビットがはみ出す
+    let overflow64 = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02];
+    invalid(VariableUInt64::read(&mut Cursor::new(&overflow64[..])).map(|v| v.0), &overflow64);
+    invalid(VariableInt64::read(&mut Cursor::new(&overflow64[..])).map(|v| v.0 as u64), &overflow64);
+    let overflow32 = [0xff, 0xff, 0xff, 0xff, 0x10];
+    invalid(VariableUInt::read(&mut Cursor::new(&overflow32[..])).map(|v| v.0 as u64), &overflow32);
+    // u32に収まらない値
+    let u64_value = bytes(|w| VariableUInt64(u32::max_value() as u64 + 1).write(w));
+    invalid(VariableUInt::read(&mut Cursor::new(&u64_value)).map(|v| v.0 as u64), &u64_value);
+}
+#[test]
+fn truncated_value_is_eof() {
+    match VariableUInt64::read(&mut Cursor::new(&[0x80, 0x80][..])) {
+        Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => (),
+        r => panic!("unexpected {:?}", r.map(|v| v.0).map_err(|e| e.kind()))
+    }
+}
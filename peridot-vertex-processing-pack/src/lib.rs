@@ -2,12 +2,14 @@
 
 extern crate bedrock;
 extern crate peridot_serialization_utils; use peridot_serialization_utils::*;
+#[macro_use] extern crate peridot_serialization_derive;
 
 use bedrock as br;
-use std::io::{Write, BufRead, Seek, SeekFrom, Result as IOResult, Error as IOError, ErrorKind, Cursor};
+use std::io::{Write, Read, BufRead, Seek, SeekFrom, Result as IOResult, Error as IOError, ErrorKind, Take};
 use std::io::BufReader;
 use std::fs::File;
 use std::path::Path;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PvpContainer {
@@ -24,67 +26,97 @@ impl PvpContainer {
         }
     }
 
-    pub fn write<W: Write>(&self, writer: &mut W) -> IOResult<()> {
-        writer.write(b"PVP\x01")?;  // ヘッダ(シグネチャとバージョン)
-
-        // バイナリを裏で構築しつつオフセット値を書き出す
-        let mut blob = Cursor::new(Vec::new());
-        self.vertex_bindings.binary_serialize(&mut blob)?;
-        VariableUInt((blob.seek(SeekFrom::Current(0))?) as _).write(writer)?;
-        self.vertex_attributes.binary_serialize(&mut blob)?;
-        VariableUInt((blob.seek(SeekFrom::Current(0))?) as _).write(writer)?;
-        self.vertex_shader.binary_serialize(&mut blob)?;
+    /// Writes the container in the version 2 layout
+    pub fn write<W: Write>(&self, writer: &mut W) -> IOResult<()> { self.write_with_sections(writer, &[]) }
+    /// Writes the container with additional sections(ids not defined in `section`).
+    /// Readers which do not know the ids skip them
+    pub fn write_with_sections<W: Write>(&self, writer: &mut W, extra_sections: &[(u32, &[u8])]) -> IOResult<()> {
+        if let Some(&(id, _)) = extra_sections.iter().find(|&&(id, _)| id <= section::FRAGMENT_SHADER) {
+            return Err(IOError::new(ErrorKind::InvalidInput, format!("Section id {} is reserved", id)));
+        }
+        writer.write_all(b"PVP\x02")?;  // ヘッダ(シグネチャとバージョン)
+        let bindings: Vec<VertexBinding> = self.vertex_bindings.iter().map(From::from).collect();
+        let attributes: Vec<VertexAttribute> = self.vertex_attributes.iter().map(From::from).collect();
+        write_section(writer, section::VERTEX_BINDINGS, &serialize_into_memory(&bindings)?)?;
+        write_section(writer, section::VERTEX_ATTRIBUTES, &serialize_into_memory(&attributes)?)?;
+        write_section(writer, section::VERTEX_SHADER, &shader_into_memory(&self.vertex_shader)?)?;
         if let Some(ref b) = self.fragment_shader {
-            VariableUInt((blob.seek(SeekFrom::Current(0))?) as _).write(writer)?;
-            b.binary_serialize(&mut blob)?;
+            write_section(writer, section::FRAGMENT_SHADER, &shader_into_memory(b)?)?;
         }
-        else { VariableUInt(0).write(writer)?; }
-
-        writer.write(&blob.into_inner()).map(drop)
+        for &(id, payload) in extra_sections { write_section(writer, id, payload)?; }
+        VariableUInt(section::END).write(writer).map(drop)
     }
 }
+/// 長さの後に内容を置く(`PascalBytes`と同じ形式)
+fn write_section<W: Write>(writer: &mut W, id: u32, payload: &[u8]) -> IOResult<()> {
+    VariableUInt(id).write(writer)?;
+    PascalBytes(payload).write(writer).map(drop)
+}
 
+/// Section ids of the version 2 layout.
+///
+/// A version 2 container is `PVP\x02` followed by sections(id as `VariableUInt`, payload length as `VariableUInt64`, payload)
+/// terminated by `END`. Payloads of the sections here have the same encodings as the version 1 layout.
+/// New kinds of data get new ids, so that old readers skip them
+pub mod section {
+    pub const END: u32 = 0;
+    pub const VERTEX_BINDINGS: u32 = 1;
+    pub const VERTEX_ATTRIBUTES: u32 = 2;
+    pub const VERTEX_SHADER: u32 = 3;
+    /// Optional
+    pub const FRAGMENT_SHADER: u32 = 4;
+}
+
+/// Reads version 1 and version 2 containers
 pub struct PvpContainerReader<R: BufRead + Seek> {
-    vb_offset: usize, va_offset: usize, vsh_offset: usize, fsh_offset: Option<usize>,
+    /// section id -> (位置, 長さ)。version 1のファイルはオフセットから同じ形に直す
+    sections: BTreeMap<u32, (u64, u64)>,
     reader: R
 }
 impl<R: BufRead + Seek> PvpContainerReader<R> {
     pub fn new(mut reader: R) -> IOResult<Self> {
         let mut signature = [0u8; 4];
         reader.read_exact(&mut signature)?;
-        if &signature != b"PVP\x01" {
-            return Err(IOError::new(ErrorKind::Other, "Signature mismatch: Invalid or corrupted Peridot Vertex Processing file"));
+        let sections = match &signature {
+            b"PVP\x01" => read_v1_sections(&mut reader)?,
+            b"PVP\x02" => read_sections(&mut reader)?,
+            _ => return Err(IOError::new(ErrorKind::Other, "Signature mismatch: Invalid or corrupted Peridot Vertex Processing file"))
+        };
+        for &id in &[section::VERTEX_BINDINGS, section::VERTEX_ATTRIBUTES, section::VERTEX_SHADER] {
+            if !sections.contains_key(&id) { return Err(corrupted(&format!("Missing section {}", id))); }
         }
 
-        let VariableUInt(va_offset) = VariableUInt::read(&mut reader)?;
-        let VariableUInt(vsh_offset) = VariableUInt::read(&mut reader)?;
-        let VariableUInt(fsh_offset_0) = VariableUInt::read(&mut reader)?;
-        let blob_offset = reader.seek(SeekFrom::Current(0))? as usize;
-
-        return Ok(PvpContainerReader {
-            vb_offset: blob_offset as _, va_offset: (va_offset + blob_offset as u32) as _,
-            vsh_offset: (vsh_offset + blob_offset as u32) as _,
-            fsh_offset: if fsh_offset_0 == 0 { None } else { Some((fsh_offset_0 + blob_offset as u32) as _) },
-            reader
-        });
+        return Ok(PvpContainerReader { sections, reader });
     }
 
     pub fn read_vertex_bindings(&mut self) -> IOResult<Vec<br::vk::VkVertexInputBindingDescription>> {
-        self.reader.seek(SeekFrom::Start(self.vb_offset as _))?;
-        Vec::<_>::binary_unserialize(&mut self.reader)
+        let bindings = Vec::<VertexBinding>::deserialize(&mut self.section_reader(section::VERTEX_BINDINGS)?)?;
+        return Ok(bindings.into_iter().map(From::from).collect());
     }
     pub fn read_vertex_attributes(&mut self) -> IOResult<Vec<br::vk::VkVertexInputAttributeDescription>> {
-        self.reader.seek(SeekFrom::Start(self.va_offset as _))?;
-        Vec::<_>::binary_unserialize(&mut self.reader)
+        let attributes = Vec::<VertexAttribute>::deserialize(&mut self.section_reader(section::VERTEX_ATTRIBUTES)?)?;
+        return Ok(attributes.into_iter().map(From::from).collect());
     }
     pub fn read_vertex_shader(&mut self) -> IOResult<Vec<u8>> {
-        self.reader.seek(SeekFrom::Start(self.vsh_offset as _))?;
-        Vec::<u8>::binary_unserialize(&mut self.reader)
+        read_shader(&mut self.section_reader(section::VERTEX_SHADER)?)
     }
-    pub fn is_fragment_stage_provided(&mut self) -> bool { self.fsh_offset.is_some() }
+    pub fn is_fragment_stage_provided(&mut self) -> bool { self.has_section(section::FRAGMENT_SHADER) }
     pub fn read_fragment_shader(&mut self) -> IOResult<Vec<u8>> {
-        self.reader.seek(SeekFrom::Start(self.fsh_offset.unwrap() as _))?;
-        Vec::<u8>::binary_unserialize(&mut self.reader)
+        read_shader(&mut self.section_reader(section::FRAGMENT_SHADER)?)
+    }
+
+    pub fn has_section(&self, id: u32) -> bool { self.sections.contains_key(&id) }
+    /// Payload of the section(e.g. added by `PvpContainer::write_with_sections`). None if the container does not have it
+    pub fn read_section(&mut self, id: u32) -> IOResult<Option<Vec<u8>>> {
+        if !self.has_section(id) { return Ok(None); }
+        let (_, length) = self.sections[&id];
+        return read_bytes(&mut self.section_reader(id)?, length).map(Some);
+    }
+    /// 内容の外を読まないように区切る
+    fn section_reader(&mut self, id: u32) -> IOResult<Take<&mut R>> {
+        let (offset, length) = *self.sections.get(&id).ok_or_else(|| corrupted(&format!("Missing section {}", id)))?;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        return Ok((&mut self.reader).take(length));
     }
 
     pub fn into_container(mut self) -> IOResult<PvpContainer> {
@@ -96,73 +128,107 @@ impl<R: BufRead + Seek> PvpContainerReader<R> {
         })
     }
 }
+/// Skips payloads, recording where sections are
+fn read_sections<R: BufRead + Seek>(reader: &mut R) -> IOResult<BTreeMap<u32, (u64, u64)>> {
+    let mut sections = BTreeMap::new();
+    loop {
+        let VariableUInt(id) = VariableUInt::read(reader)?;
+        if id == section::END { return Ok(sections); }
+        let VariableUInt64(length) = VariableUInt64::read(reader)?;
+        let offset = reader.seek(SeekFrom::Current(0))?;
+        let end = offset.checked_add(length).ok_or_else(|| corrupted("Section length overflow"))?;
+        // 同じidが複数ある場合は最初のものを使う
+        sections.entry(id).or_insert((offset, length));
+        reader.seek(SeekFrom::Start(end))?;
+    }
+}
+/// version 1: offsets of the vertex attributes, the vertex shader and the fragment shader(0 if not provided)
+/// relative to the blob which starts from the vertex bindings
+fn read_v1_sections<R: BufRead + Seek>(reader: &mut R) -> IOResult<BTreeMap<u32, (u64, u64)>> {
+    let VariableUInt64(va_offset) = VariableUInt64::read(reader)?;
+    let VariableUInt64(vsh_offset) = VariableUInt64::read(reader)?;
+    let VariableUInt64(fsh_offset) = VariableUInt64::read(reader)?;
+    let blob_offset = reader.seek(SeekFrom::Current(0))?;
+    let blob_length = reader.seek(SeekFrom::End(0))?.saturating_sub(blob_offset);
+
+    let mut bounds = vec![(section::VERTEX_BINDINGS, 0), (section::VERTEX_ATTRIBUTES, va_offset), (section::VERTEX_SHADER, vsh_offset)];
+    if fsh_offset != 0 { bounds.push((section::FRAGMENT_SHADER, fsh_offset)); }
+    let mut sections = BTreeMap::new();
+    for (n, &(id, offset)) in bounds.iter().enumerate() {
+        let end = bounds.get(n + 1).map_or(blob_length, |&(_, o)| o);
+        // 壊れたオフセットで位置があふれないように
+        let length = end.checked_sub(offset).ok_or_else(|| corrupted("Offsets out of order"))?;
+        sections.insert(id, (blob_offset + offset, length));
+    }
+    return Ok(sections);
+}
+fn corrupted(reason: &str) -> IOError {
+    IOError::new(ErrorKind::InvalidData, format!("{}: corrupted Peridot Vertex Processing file", reason))
+}
 impl PvpContainerReader<BufReader<File>> {
     pub fn from_file<P: AsRef<Path>>(path: P) -> IOResult<Self> {
         File::open(path).and_then(|fp| Self::new(BufReader::new(fp)))
     }
 }
+/// 共有メモリやマップされたファイルからシェーダバイナリを複製せずに取り出す
+impl<'d> PvpContainerReader<SliceReader<'d>> {
+    pub fn from_slice(data: &'d [u8]) -> IOResult<Self> { Self::new(SliceReader::new(data)) }
+    /// The vertex shader borrowed from the input
+    pub fn vertex_shader_slice(&mut self) -> IOResult<&'d [u8]> {
+        let payload = self.section_slice(section::VERTEX_SHADER)?.unwrap();
+        PascalBytes::read(&mut SliceReader::new(payload)).map(|PascalBytes(b)| b)
+    }
+    /// The fragment shader borrowed from the input. None if the fragment stage is not provided
+    pub fn fragment_shader_slice(&mut self) -> IOResult<Option<&'d [u8]>> {
+        let payload = match self.section_slice(section::FRAGMENT_SHADER)? { Some(p) => p, None => return Ok(None) };
+        PascalBytes::read(&mut SliceReader::new(payload)).map(|PascalBytes(b)| Some(b))
+    }
+    /// Payload of the section borrowed from the input
+    pub fn section_slice(&mut self, id: u32) -> IOResult<Option<&'d [u8]>> {
+        let (offset, length) = match self.sections.get(&id) { Some(&s) => s, None => return Ok(None) };
+        self.reader.seek(SeekFrom::Start(offset))?;
+        return self.reader.read_borrowed(length).map(Some);
+    }
+}
 
-trait BinarySerializeVkStructures {
-    fn binary_serialize<W: Write>(&self, sink: &mut W) -> IOResult<usize>;
-    fn binary_unserialize<R: BufRead>(source: &mut R) -> IOResult<Self> where Self: Sized;
-    fn serialize_into_memory(&self) -> IOResult<Vec<u8>> {
-        let mut sink = Cursor::new(Vec::new());
-        self.binary_serialize(&mut sink).map(|_| sink.into_inner())
-    }
-}
-impl BinarySerializeVkStructures for br::vk::VkVertexInputBindingDescription {
-    fn binary_serialize<W: Write>(&self, sink: &mut W) -> IOResult<usize> {
-        VariableUInt(self.inputRate as _).write(sink)
-            .and_then(|w0| VariableUInt(self.binding as _).write(sink).map(move |w1| w1 + w0))
-            .and_then(|w0| VariableUInt(self.stride as _).write(sink).map(move |w1| w1 + w0))
-    }
-    fn binary_unserialize<R: BufRead>(source: &mut R) -> IOResult<Self> where Self: Sized {
-        let VariableUInt(input_rate) = VariableUInt::read(source)?;
-        let VariableUInt(binding) = VariableUInt::read(source)?;
-        let VariableUInt(stride) = VariableUInt::read(source)?;
-        return Ok(br::vk::VkVertexInputBindingDescription {
-            inputRate: input_rate as _, binding: binding as _, stride: stride as _
-        });
-    }
-}
-impl BinarySerializeVkStructures for br::vk::VkVertexInputAttributeDescription {
-    fn binary_serialize<W: Write>(&self, sink: &mut W) -> IOResult<usize> {
-        VariableUInt(self.location as _).write(sink)
-            .and_then(|w0| VariableUInt(self.binding as _).write(sink).map(move |w1| w1 + w0))
-            .and_then(|w0| VariableUInt(self.offset as _).write(sink).map(move |w1| w1 + w0))
-            .and_then(|w0| VariableUInt(self.format as _).write(sink).map(move |w1| w1 + w0))
-    }
-    fn binary_unserialize<R: BufRead>(source: &mut R) -> IOResult<Self> where Self: Sized {
-        let VariableUInt(location) = VariableUInt::read(source)?;
-        let VariableUInt(binding) = VariableUInt::read(source)?;
-        let VariableUInt(offset) = VariableUInt::read(source)?;
-        let VariableUInt(format) = VariableUInt::read(source)?;
-        return Ok(br::vk::VkVertexInputAttributeDescription {
-            location: location as _, binding: binding as _, offset: offset as _,
-            format: format as _
-        });
-    }
-}
-impl<T: BinarySerializeVkStructures> BinarySerializeVkStructures for Vec<T> {
-    fn binary_serialize<W: Write>(&self, sink: &mut W) -> IOResult<usize> {
-        let mut write_bytes = VariableUInt(self.len() as _).write(sink)?;
-        for x in self { write_bytes += x.binary_serialize(sink)?; } return Ok(write_bytes);
-    }
-    fn binary_unserialize<R: BufRead>(source: &mut R) -> IOResult<Self> where Self: Sized {
-        let VariableUInt(element_count) = VariableUInt::read(source)?;
-        let mut vs = Vec::with_capacity(element_count as _);
-        for _ in 0 .. element_count { vs.push(T::binary_unserialize(source)?); }
-        return Ok(vs);
-    }
-}
-impl BinarySerializeVkStructures for Vec<u8> {
-    fn binary_serialize<W: Write>(&self, sink: &mut W) -> IOResult<usize> {
-        VariableUInt(self.len() as _).write(sink)
-            .and_then(|w0| sink.write_all(self).map(move |_| self.len() + w0))
-    }
-    fn binary_unserialize<R: BufRead>(source: &mut R) -> IOResult<Self> where Self: Sized {
-        let VariableUInt(element_count) = VariableUInt::read(source)?;
-        let mut buf = vec![0u8; element_count as usize];
-        source.read_exact(&mut buf).map(|_| buf)
+/// `VkVertexInputBindingDescription` in the container
+#[derive(PeridotSerialize, PeridotDeserialize)]
+struct VertexBinding { input_rate: u32, binding: u32, stride: u32 }
+/// `VkVertexInputAttributeDescription` in the container
+#[derive(PeridotSerialize, PeridotDeserialize)]
+struct VertexAttribute { location: u32, binding: u32, offset: u32, format: u32 }
+impl<'a> From<&'a br::vk::VkVertexInputBindingDescription> for VertexBinding {
+    fn from(d: &'a br::vk::VkVertexInputBindingDescription) -> Self {
+        VertexBinding { input_rate: d.inputRate as _, binding: d.binding as _, stride: d.stride as _ }
+    }
+}
+impl From<VertexBinding> for br::vk::VkVertexInputBindingDescription {
+    fn from(b: VertexBinding) -> Self {
+        br::vk::VkVertexInputBindingDescription { inputRate: b.input_rate as _, binding: b.binding as _, stride: b.stride as _ }
+    }
+}
+impl<'a> From<&'a br::vk::VkVertexInputAttributeDescription> for VertexAttribute {
+    fn from(d: &'a br::vk::VkVertexInputAttributeDescription) -> Self {
+        VertexAttribute { location: d.location as _, binding: d.binding as _, offset: d.offset as _, format: d.format as _ }
     }
 }
+impl From<VertexAttribute> for br::vk::VkVertexInputAttributeDescription {
+    fn from(a: VertexAttribute) -> Self {
+        br::vk::VkVertexInputAttributeDescription {
+            location: a.location as _, binding: a.binding as _, offset: a.offset as _, format: a.format as _
+        }
+    }
+}
+fn serialize_into_memory<T: PeridotSerialize>(value: &T) -> IOResult<Vec<u8>> {
+    let mut sink = Vec::new();
+    value.serialize(&mut sink).map(|_| sink)
+}
+/// シェーダは`PascalBytes`の形式で格納する
+fn shader_into_memory(code: &[u8]) -> IOResult<Vec<u8>> {
+    let mut sink = Vec::new();
+    PascalBytes(code).write(&mut sink).map(|_| sink)
+}
+fn read_shader<R: BufRead>(source: &mut R) -> IOResult<Vec<u8>> {
+    let VariableUInt64(length) = VariableUInt64::read(source)?;
+    read_bytes(source, length)
+}
@@ -0,0 +1,83 @@
+//! Containers written by older and current versions
+
+extern crate bedrock;
+extern crate peridot_vertex_processing_pack as pvp;
+
+use bedrock as br;
+use pvp::{PvpContainer, PvpContainerReader};
+use std::io::Cursor;
+use std::path::Path;
+
+/// SPIR-Vのマジックナンバー(リトルエンディアン)
+const SPIRV_MAGIC: [u8; 4] = [0x03, 0x02, 0x23, 0x07];
+
+fn sample() -> PvpContainer {
+    PvpContainer {
+        vertex_bindings: vec![
+            br::vk::VkVertexInputBindingDescription { binding: 0, stride: 16, inputRate: br::vk::VK_VERTEX_INPUT_RATE_VERTEX },
+            br::vk::VkVertexInputBindingDescription { binding: 1, stride: 300, inputRate: br::vk::VK_VERTEX_INPUT_RATE_INSTANCE }
+        ],
+        vertex_attributes: vec![
+            br::vk::VkVertexInputAttributeDescription { location: 0, binding: 0, offset: 0, format: br::vk::VK_FORMAT_R32G32B32A32_SFLOAT },
+            br::vk::VkVertexInputAttributeDescription { location: 1, binding: 1, offset: 8, format: br::vk::VK_FORMAT_R32G32_SFLOAT }
+        ],
+        vertex_shader: (0 .. 200).collect(),
+        fragment_shader: Some(b"fragment".to_vec())
+    }
+}
+fn write(c: &PvpContainer) -> Vec<u8> { let mut bytes = Vec::new(); c.write(&mut bytes).unwrap(); return bytes; }
+
+#[test]
+fn version_1_asset_reads() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets/shaders/pass.pvp");
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(&bytes[..4], b"PVP\x01");
+
+    let c = PvpContainerReader::from_file(&path).unwrap().into_container().unwrap();
+    assert!(!c.vertex_bindings.is_empty() && !c.vertex_attributes.is_empty());
+    assert_eq!(&c.vertex_shader[..4], SPIRV_MAGIC);
+    assert_eq!(&c.fragment_shader.as_ref().unwrap()[..4], SPIRV_MAGIC);
+    // 借用して読んでも同じ内容になる
+    let mut borrowed = PvpContainerReader::from_slice(&bytes).unwrap();
+    assert_eq!(borrowed.vertex_shader_slice().unwrap(), &c.vertex_shader[..]);
+    assert_eq!(borrowed.fragment_shader_slice().unwrap(), c.fragment_shader.as_ref().map(|b| &b[..]));
+
+    // version 2で書き直しても同じ内容
+    let rewritten = write(&c);
+    assert_eq!(&rewritten[..4], b"PVP\x02");
+    assert_eq!(PvpContainerReader::from_slice(&rewritten).unwrap().into_container().unwrap(), c);
+}
+#[test]
+fn version_2_round_trip() {
+    let c = sample();
+    let bytes = write(&c);
+    assert_eq!(PvpContainerReader::new(Cursor::new(&bytes)).unwrap().into_container().unwrap(), c);
+    let mut borrowed = PvpContainerReader::from_slice(&bytes).unwrap();
+    assert_eq!(borrowed.vertex_shader_slice().unwrap(), &c.vertex_shader[..]);
+    assert_eq!(borrowed.fragment_shader_slice().unwrap(), Some(&b"fragment"[..]));
+
+    let vertex_only = PvpContainer { fragment_shader: None, ..sample() };
+    let mut reader = PvpContainerReader::new(Cursor::new(write(&vertex_only))).unwrap();
+    assert!(!reader.is_fragment_stage_provided());
+    assert!(reader.read_fragment_shader().is_err());
+    assert_eq!(reader.into_container().unwrap(), vertex_only);
+    assert_eq!(PvpContainerReader::new(Cursor::new(write(&PvpContainer::empty()))).unwrap().into_container().unwrap(),
+        PvpContainer::empty());
+}
+#[test]
+fn unknown_sections_are_skipped() {
+    let c = sample();
+    let mut bytes = Vec::new();
+    c.write_with_sections(&mut bytes, &[(100, b"extra"), (5, b""), (100, b"duplicated")]).unwrap();
+
+    let mut reader = PvpContainerReader::new(Cursor::new(&bytes)).unwrap();
+    assert_eq!(reader.read_section(100).unwrap(), Some(b"extra".to_vec()));
+    assert_eq!(reader.read_section(5).unwrap(), Some(Vec::new()));
+    assert_eq!(reader.read_section(6).unwrap(), None);
+    assert_eq!(reader.into_container().unwrap(), c);
+    assert_eq!(PvpContainerReader::from_slice(&bytes).unwrap().section_slice(100).unwrap(), Some(&b"extra"[..]));
+
+    for &id in &[0, 1, 4] {
+        assert!(c.write_with_sections(&mut Vec::new(), &[(id, b"")]).is_err(), "section id {} was accepted", id);
+    }
+}
@@ -0,0 +1,28 @@
+//! Inputs in the fuzzing corpus(`fuzz/corpus/pvp_read`) must be rejected or read without panics
+
+extern crate peridot_vertex_processing_pack as pvp;
+
+use std::fs::{read, read_dir};
+use std::io::Cursor;
+use std::path::Path;
+
+#[test]
+fn pvp_read_corpus() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("../fuzz/corpus/pvp_read");
+    for f in read_dir(corpus).unwrap() {
+        let path = f.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let bytes = read(&path).unwrap();
+        let read = pvp::PvpContainerReader::new(Cursor::new(&bytes)).and_then(|r| r.into_container());
+        let borrowed = pvp::PvpContainerReader::from_slice(&bytes).and_then(|mut r| {
+            r.vertex_shader_slice().and_then(|_| r.fragment_shader_slice()).map(drop)
+        });
+        if name.starts_with("regression_") {
+            assert!(read.is_err(), "{} was read without errors", name);
+        }
+        else {
+            read.unwrap_or_else(|e| panic!("{}: {}", name, e));
+            borrowed.unwrap_or_else(|e| panic!("{} (borrowed): {}", name, e));
+        }
+    }
+}
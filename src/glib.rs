@@ -12,6 +12,7 @@ use std::borrow::Cow;
 use peridot_vertex_processing_pack::*;
 use std::rc::Rc;
 use std::marker::PhantomData;
+use std::any::Any;
 
 // fn main() { env_logger::init(); Game::launch(); }
 
@@ -60,6 +61,52 @@ pub struct Game<AL: AssetLoader, PRT: PlatformRenderTarget>
 impl<AL: AssetLoader, PRT: PlatformRenderTarget> Game<AL, PRT> {
     pub const NAME: &'static str = "Infinitesweeper";
     pub const VERSION: (u32, u32, u32) = (0, 1, 0);
+
+    fn framebuffer_size(e: &Engine<Self, AL, PRT>) -> br::vk::VkRect2D {
+        br::Extent2D::clone(e.backbuffers()[0].size().as_ref()).into()
+    }
+    fn create_pass_pipeline(e: &Engine<Self, AL, PRT>, rp: &br::RenderPass, res: &MainResources, pvp_pass: PvpContainer)
+            -> br::Result<LayoutedPipeline> {
+        let framebuffer_size = Self::framebuffer_size(e);
+        let filling_viewport = br::vk::VkViewport {
+            x: framebuffer_size.offset.x as _, y: framebuffer_size.offset.y as _,
+            width: framebuffer_size.extent.width as _, height: framebuffer_size.extent.height as _,
+            minDepth: 0.0, maxDepth: 1.0
+        };
+        let pass_shaders = PvpShaderModules::new(&e.graphics_device(), pvp_pass)?;
+        let u0_layout: Rc<_> = br::PipelineLayout::new(&e.graphics_device(), &[&res.dsl_u0],
+            &[(br::ShaderStage::VERTEX, 0 .. size_of::<VertexPlacementUniformData>() as _)])?.into();
+        let screen_spec = ShaderSpecConstants {
+            screen_aspect_wh: filling_viewport.width / filling_viewport.height,
+            emboss_thickness: 0.05
+        };
+        let pass_gp = br::GraphicsPipelineBuilder::new(&u0_layout, (rp, 0))
+            .vertex_processing({
+                let mut vps = pass_shaders.generate_vps(br::vk::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST);
+                vps.mod_vertex_shader().specinfo = screen_spec.spec_info().into();
+                vps.mod_fragment_shader().unwrap().specinfo = screen_spec.spec_info_frag().into();
+                vps
+            })
+            .fixed_viewport_scissors(br::DynamicArrayState::Static(&[filling_viewport]), br::DynamicArrayState::Static(&[framebuffer_size.clone()]))
+            .add_attachment_blend(br::AttachmentColorBlendState::noblend())
+            .create(&e.graphics_device(), None)?;
+        return Ok(LayoutedPipeline::combine(pass_gp, &u0_layout));
+    }
+    fn record_framebuffer_commands(&self, e: &Engine<Self, AL, PRT>) {
+        let framebuffer_size = Self::framebuffer_size(e);
+        for (fb, cb) in self.framebuffers.iter().zip(self.framebuffer_commands.iter()) {
+            let mut rec = cb.begin().expect("Beginning Recording commands");
+            rec.begin_render_pass(&self.rp, fb, framebuffer_size.clone(), &[br::ClearValue::Color([0.0; 4])], true);
+            self.pass_gp.bind(&mut rec);
+            self.res.stack.setup_for_draw_chunked_rects(&self.res.buffer, &mut rec);
+            rec.bind_graphics_descriptor_sets(0, &[self.res.dset_render_offset], &[]);
+            for v in VPUD {
+                rec .push_graphics_constant(br::ShaderStage::VERTEX, 0, v)
+                    .draw_indexed((6 * CHUNK_SIZE * CHUNK_SIZE) as _, 1, 0, 0, 0);
+            }
+            rec.end_render_pass();
+        }
+    }
 }
 impl<AL: AssetLoader, PRT: PlatformRenderTarget> EngineEvents<AL, PRT> for Game<AL, PRT> {
     fn init(e: &Engine<Self, AL, PRT>) -> Self
@@ -74,12 +121,6 @@ impl<AL: AssetLoader, PRT: PlatformRenderTarget> EngineEvents<AL, PRT> for Game<
             .create(&e.graphics_device()).expect("RenderPass");
         let framebuffers: Vec<_> = e.backbuffers().iter()
             .map(|v| br::Framebuffer::new(&rp, &[v], v.size(), 1).expect("Framebuffer")).collect();
-        let framebuffer_size: br::vk::VkRect2D = br::Extent2D::clone(render_area.as_ref()).into();
-        let filling_viewport = br::vk::VkViewport {
-            x: framebuffer_size.offset.x as _, y: framebuffer_size.offset.y as _,
-            width: framebuffer_size.extent.width as _, height: framebuffer_size.extent.height as _,
-            minDepth: 0.0, maxDepth: 1.0
-        };
 
         let (mut tb, mut dsub) = (TransferBatch::new(), DescriptorSetUpdateBatch::new());
         let res = MainResources::init(e, &mut tb, &mut dsub).unwrap();
@@ -94,39 +135,12 @@ impl<AL: AssetLoader, PRT: PlatformRenderTarget> EngineEvents<AL, PRT> for Game<
         }).unwrap();
 
         let pvp_pass: PvpContainer = e.load("shaders.pass").expect("Asset not found");
-        let pass_shaders = PvpShaderModules::new(&e.graphics_device(), pvp_pass).unwrap();
-        let u0_layout: Rc<_> = br::PipelineLayout::new(&e.graphics_device(), &[&res.dsl_u0],
-            &[(br::ShaderStage::VERTEX, 0 .. size_of::<VertexPlacementUniformData>() as _)]).unwrap().into();
-        let screen_spec = ShaderSpecConstants {
-            screen_aspect_wh: filling_viewport.width / filling_viewport.height,
-            emboss_thickness: 0.05
-        };
-        let pass_gp = br::GraphicsPipelineBuilder::new(&u0_layout, (&rp, 0))
-            .vertex_processing({
-                let mut vps = pass_shaders.generate_vps(br::vk::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST);
-                vps.mod_vertex_shader().specinfo = screen_spec.spec_info().into();
-                vps.mod_fragment_shader().unwrap().specinfo = screen_spec.spec_info_frag().into();
-                vps
-            })
-            .fixed_viewport_scissors(br::DynamicArrayState::Static(&[filling_viewport]), br::DynamicArrayState::Static(&[framebuffer_size.clone()]))
-            .add_attachment_blend(br::AttachmentColorBlendState::noblend())
-            .create(&e.graphics_device(), None).unwrap();
-        let pass_gp = LayoutedPipeline::combine(pass_gp, &u0_layout);
+        // シェーダを再コンパイルしたら作り直す(asset_reloaded)
+        #[cfg(debug_assertions)] e.watch_asset::<PvpContainer>("shaders.pass");
+        let pass_gp = Self::create_pass_pipeline(e, &rp, &res, pvp_pass).unwrap();
 
         let framebuffer_commands = CommandBundle::new(&e.graphics(), CBSubmissionType::Graphics, framebuffers.len())
             .expect("Framebuffer CommandBundle");
-        for (fb, cb) in framebuffers.iter().zip(framebuffer_commands.iter()) {
-            let mut rec = cb.begin().expect("Beginning Recording commands");
-            rec.begin_render_pass(&rp, fb, framebuffer_size.clone(), &[br::ClearValue::Color([0.0; 4])], true);
-            pass_gp.bind(&mut rec);
-            res.stack.setup_for_draw_chunked_rects(&res.buffer, &mut rec);
-            rec.bind_graphics_descriptor_sets(0, &[res.dset_render_offset], &[]);
-            for v in VPUD {
-                rec .push_graphics_constant(br::ShaderStage::VERTEX, 0, v)
-                    .draw_indexed((6 * CHUNK_SIZE * CHUNK_SIZE) as _, 1, 0, 0, 0);
-            }
-            rec.end_render_pass();
-        }
         let mut update_commands = Vec::with_capacity(framebuffers.len());
         for _ in 0 .. framebuffers.len() {
             update_commands.push(CommandBundle::new(&e.graphics(), CBSubmissionType::Transfer, 1)
@@ -136,10 +150,25 @@ impl<AL: AssetLoader, PRT: PlatformRenderTarget> EngineEvents<AL, PRT> for Game<
             let _ = update_commands[0].begin().expect("Beginning Recording commands");
         }*/
 
-        return Game {
+        let this = Game {
             rp, framebuffers, framebuffer_commands, update_commands, pass_gp, res, _p: PhantomData,
             render_offset: [0.0; 2], drag_vec: (0.0, 0.0)
         };
+        this.record_framebuffer_commands(e);
+        return this;
+    }
+    fn asset_reloaded(&mut self, e: &Engine<Self, AL, PRT>, path: &str, asset: Box<Any>) {
+        if path != "shaders.pass" { return; }
+        let pvp_pass = match asset.downcast::<PvpContainer>() { Ok(c) => *c, Err(_) => return };
+        let pass_gp = match Self::create_pass_pipeline(e, &self.rp, &self.res, pvp_pass) {
+            Ok(p) => p, Err(err) => { warn!("Rebuilding the pipeline failed: {:?}", err); return; }
+        };
+        // 記録済みのコマンドが古いパイプラインを使い終わるのを待つ
+        e.graphics_device().wait().expect("Waiting for the device");
+        self.pass_gp = pass_gp;
+        self.framebuffer_commands.reset().expect("Resetting Framebuffer CommandBundle");
+        self.record_framebuffer_commands(e);
+        info!("Pipeline rebuilt with the reloaded shaders");
     }
     fn update(&mut self, e: &Engine<Self, AL, PRT>, on_backbuffer_of: u32) -> (Option<br::SubmissionBatch>, br::SubmissionBatch) {
         const DRAG: f32 = 0.9;
@@ -7,6 +7,7 @@ extern crate libc;
 extern crate regex;
 // #[macro_use] extern crate bitflags;
 extern crate peridot_vertex_processing_pack;
+extern crate peridot_archive;
 extern crate env_logger;
 mod peridot;
 
@@ -72,25 +73,30 @@ impl PlatformAssetLoader {
         base_path.pop(); base_path.push("assets");
         return PlatformAssetLoader { base_path }
     }
+    fn asset_path(&self, path: &str, ext: &str) -> PathBuf {
+        let mut asset_path = self.base_path.clone();
+        asset_path.extend(path.split("."));
+        asset_path.set_extension(ext);
+        return asset_path;
+    }
 }
 impl peridot::AssetLoader for PlatformAssetLoader {
     type Asset = File;
     type StreamingAsset = File;
 
     fn get(&self, path: &str, ext: &str) -> IOResult<File> {
-        let mut asset_path = self.base_path.clone();
-        asset_path.extend(path.split("."));
-        asset_path.set_extension(ext);
+        let asset_path = self.asset_path(path, ext);
         debug!("Loading Asset: {}...", asset_path.display());
         return File::open(&asset_path);
     }
     fn get_streaming(&self, path: &str, ext: &str) -> IOResult<File> {
-        let mut asset_path = self.base_path.clone();
-        asset_path.extend(path.split("."));
-        asset_path.set_extension(ext);
+        let asset_path = self.asset_path(path, ext);
         debug!("Loading Asset: {}...", asset_path.display());
         return File::open(&asset_path);
     }
+    fn modified_time(&self, path: &str, ext: &str) -> Option<std::time::SystemTime> {
+        std::fs::metadata(self.asset_path(path, ext)).and_then(|m| m.modified()).ok()
+    }
 }
 
 struct RenderTargetWindow { instance: HINSTANCE, handle: HWND }